@@ -0,0 +1,153 @@
+use crate::history::{RequestRecord, SnipeRecord};
+use std::collections::BTreeMap;
+
+/// Success rate of the snipes in which at least one account used one particular offset or
+/// spread. A snipe only counts as a success for the offset or spread of the winning request.
+pub struct Breakdown<T> {
+    pub value: T,
+    pub snipes: usize,
+    pub successes: usize,
+}
+
+impl<T> Breakdown<T> {
+    #[must_use]
+    pub fn rate(&self) -> f64 {
+        #[allow(clippy::cast_precision_loss)]
+        let rate = self.successes as f64 / self.snipes as f64;
+        rate * 100.0
+    }
+}
+
+/// Where the server started accepting requests, in milliseconds relative to the droptime
+/// on the local clock at which the request was sent.
+pub struct Boundary {
+    /// Latest send time of a request that was rejected before a winning request.
+    pub rejected: Option<f64>,
+    /// Earliest send time of a winning request.
+    pub accepted: f64,
+    /// Median of the boundaries estimated from each successful snipe.
+    pub estimate: f64,
+    /// Number of successful snipes the boundary was estimated from.
+    pub samples: usize,
+}
+
+pub struct Analysis {
+    pub snipes: usize,
+    pub successes: usize,
+    pub by_offset: Vec<Breakdown<u32>>,
+    pub by_spread: Vec<Breakdown<String>>,
+    pub boundary: Option<Boundary>,
+    /// Offset that sends the first request at the estimated boundary.
+    pub offset: Option<u32>,
+}
+
+/// Analyses past snipes. A rejected request sent before the winning request of the same
+/// snipe was too early, while one sent after it lost to the winner, so only successful
+/// snipes say anything about when the server starts accepting requests.
+#[must_use]
+pub fn analyse(records: &[SnipeRecord]) -> Analysis {
+    let successes = records.iter().filter(|record| record.success).count();
+    let windows: Vec<(Option<f64>, f64)> = records.iter().filter_map(acceptance_window).collect();
+    let boundary = if windows.is_empty() {
+        None
+    } else {
+        let mut estimates: Vec<f64> = windows
+            .iter()
+            .map(|&(rejected, accepted)| {
+                rejected.map_or(accepted, |rejected| f64::midpoint(rejected, accepted))
+            })
+            .collect();
+        estimates.sort_unstable_by(f64::total_cmp);
+        let n = estimates.len();
+        let estimate = if n.is_multiple_of(2) {
+            f64::midpoint(estimates[n / 2 - 1], estimates[n / 2])
+        } else {
+            estimates[n / 2]
+        };
+        Some(Boundary {
+            rejected: windows
+                .iter()
+                .filter_map(|&(rejected, _)| rejected)
+                .max_by(f64::total_cmp),
+            accepted: windows
+                .iter()
+                .map(|&(_, accepted)| accepted)
+                .min_by(f64::total_cmp)
+                .unwrap(),
+            estimate,
+            samples: n,
+        })
+    };
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let offset = boundary
+        .as_ref()
+        .map(|boundary| (-boundary.estimate).round().max(0.0) as u32);
+    Analysis {
+        snipes: records.len(),
+        successes,
+        by_offset: breakdown(records, |record, request| {
+            request.map_or(record.offset, |request| request.offset(record))
+        }),
+        by_spread: breakdown(records, |record, request| {
+            request
+                .map_or(&record.spread, |request| request.spread(record))
+                .to_string()
+        }),
+        boundary,
+        offset,
+    }
+}
+
+/// Returns the send time of the latest request rejected with a 403 before the winning
+/// request and the send time of the winning request. Other statuses, such as rate limits,
+/// say nothing about whether the name had dropped yet.
+fn acceptance_window(record: &SnipeRecord) -> Option<(Option<f64>, f64)> {
+    let accepted = record
+        .requests
+        .iter()
+        .filter(|request| request.status == Some(200))
+        .map(sent)
+        .min_by(f64::total_cmp)?;
+    let rejected = record
+        .requests
+        .iter()
+        .filter(|request| request.status == Some(403))
+        .map(sent)
+        .filter(|&sent| sent < accepted)
+        .max_by(f64::total_cmp);
+    Some((rejected, accepted))
+}
+
+fn sent(request: &RequestRecord) -> f64 {
+    request.sent.unwrap_or(request.send_time)
+}
+
+/// Groups snipes by the values `key` returns for each of their requests. Snipes that sent
+/// no requests are grouped by the value `key` returns for the snipe itself.
+fn breakdown<T: Ord + Clone>(
+    records: &[SnipeRecord],
+    key: impl Fn(&SnipeRecord, Option<&RequestRecord>) -> T,
+) -> Vec<Breakdown<T>> {
+    let mut groups: BTreeMap<T, Breakdown<T>> = BTreeMap::new();
+    for record in records {
+        let mut values: BTreeMap<T, bool> = BTreeMap::new();
+        for request in &record.requests {
+            *values.entry(key(record, Some(request))).or_default() |= request.status == Some(200);
+        }
+        if values.is_empty() {
+            values.insert(key(record, None), record.success);
+        }
+        for (value, success) in values {
+            let group = groups.entry(value.clone()).or_insert(Breakdown {
+                value,
+                snipes: 0,
+                successes: 0,
+            });
+            group.snipes += 1;
+            if success {
+                group.successes += 1;
+            }
+        }
+    }
+    groups.into_values().collect()
+}
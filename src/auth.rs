@@ -0,0 +1,320 @@
+use crate::{
+    config::{Account, Config, SnipeTask},
+    constants,
+    msauth::DeviceCode,
+    sniper::{SnipeJob, Sniper},
+    tokens::{self, TokenCache},
+};
+use anyhow::{bail, Context, Error, Result};
+use chrono::{DateTime, Duration, Utc};
+use std::path::Path;
+use tokio::time::sleep;
+
+/// Something that happened while signing in, for the caller to report.
+pub enum AuthEvent<'a> {
+    /// Waiting 20 seconds before the next sign in to prevent rate limiting.
+    RateLimitWait,
+    /// The token cache couldn't be read, so every account signs in again.
+    CacheIgnored(&'a Error),
+    /// The token cache couldn't be written.
+    CacheNotSaved(&'a Error),
+    /// A cached token is used instead of signing in.
+    CachedToken { email: &'a str },
+    /// The user has to enter `code` to sign in to `email`.
+    DeviceCode {
+        email: &'a str,
+        code: &'a DeviceCode,
+    },
+    /// The refresh token of `email` didn't work, so a new device code is requested.
+    RefreshTokenFailed { email: &'a str, error: &'a Error },
+    /// An account failed to sign in and was dropped from the snipe.
+    SignInFailed { task: SnipeTask, error: &'a Error },
+    /// An account can't change its name and was dropped from the snipe.
+    NotEligible { email: &'a str, error: &'a Error },
+    /// A bearer token from the config expires at `expires` before the snipe, or it's
+    /// unknown when it expires.
+    BearerExpiring {
+        label: &'a str,
+        expires: Option<DateTime<Utc>>,
+    },
+    /// The tokens of `labels` expire before the snipe and are replaced at `at`.
+    SigningInAgain {
+        labels: &'a [String],
+        at: DateTime<Utc>,
+    },
+    /// There's no time left to sign in to `label` again before the snipe.
+    NoTimeToSignInAgain { label: &'a str },
+    /// Signing in to `label` again failed, so its old token is used.
+    SignInAgainFailed { label: &'a str, error: &'a Error },
+    /// The tokens of `labels` expire before the snipe even after signing in again.
+    StillExpiring { labels: &'a [String] },
+}
+
+/// Signs in to accounts, reusing the tokens cached by earlier runs, and keeps their tokens
+/// valid until the snipe. Everything worth telling the user is passed to `on_event`.
+pub struct Authenticator<'a> {
+    sniper: &'a Sniper,
+    task: SnipeTask,
+    cache: TokenCache,
+    on_event: &'a dyn Fn(&AuthEvent<'_>) -> Result<()>,
+}
+
+impl<'a> Authenticator<'a> {
+    /// Loads the token cache of `config`, starting over with an empty one if it can't be
+    /// read.
+    pub fn new(
+        sniper: &'a Sniper,
+        config: &Config,
+        on_event: &'a dyn Fn(&AuthEvent<'_>) -> Result<()>,
+    ) -> Result<Self> {
+        let path = Path::new(&config.token_cache);
+        let services = &config.endpoints.minecraft_services;
+        let cache = match TokenCache::load(path, services, config.mode) {
+            Ok(cache) => cache,
+            Err(error) => {
+                on_event(&AuthEvent::CacheIgnored(&error))?;
+                TokenCache::empty(path, services, config.mode)
+            }
+        };
+        Ok(Self {
+            sniper,
+            task: config.mode,
+            cache,
+            on_event,
+        })
+    }
+
+    /// Writes the token cache to disk. Failing to do so only costs signing in again next
+    /// time, so it's reported rather than returned.
+    pub fn save(&self) -> Result<()> {
+        if let Err(error) = self.cache.save() {
+            (self.on_event)(&AuthEvent::CacheNotSaved(&error))?;
+        }
+        Ok(())
+    }
+
+    pub async fn rate_limit_wait(&self) -> Result<()> {
+        (self.on_event)(&AuthEvent::RateLimitWait)?;
+        sleep(std::time::Duration::from_secs(20)).await;
+        Ok(())
+    }
+
+    /// Signs in to `account` and caches its token. Accounts that sign in with a device code
+    /// reuse the refresh token of their last sign in, or else wait for a new code to be
+    /// entered unless `can_prompt` is false.
+    pub async fn authenticate(&mut self, account: &Account, can_prompt: bool) -> Result<String> {
+        let Some(email) = &account.email else {
+            return self.sniper.authenticate(account, self.task).await;
+        };
+        // Tokens of accounts from the vault are as sensitive as the vault's contents
+        let persist = account.vault.is_none();
+        if !account.device_code {
+            let token = self.sniper.authenticate(account, self.task).await?;
+            self.cache.insert(email, &token, None, persist);
+            return Ok(token);
+        }
+        if let Some(refresh_token) = self.cache.refresh_token(email) {
+            match self.sniper.refresh_device_sign_in(refresh_token).await {
+                Ok(sign_in) => {
+                    self.cache.insert(
+                        email,
+                        &sign_in.bearer_token,
+                        Some(&sign_in.refresh_token),
+                        persist,
+                    );
+                    return Ok(sign_in.bearer_token);
+                }
+                Err(error) if !can_prompt => {
+                    return Err(error.context(format!("Failed to refresh the sign in of {email}")));
+                }
+                Err(error) => (self.on_event)(&AuthEvent::RefreshTokenFailed {
+                    email,
+                    error: &error,
+                })?,
+            }
+        }
+        if !can_prompt {
+            bail!("{email} has no refresh token, so a new device code has to be entered by hand");
+        }
+        let code = self.sniper.request_device_code().await?;
+        (self.on_event)(&AuthEvent::DeviceCode { email, code: &code })?;
+        let sign_in = self
+            .sniper
+            .wait_for_device_code(&code)
+            .await
+            .with_context(|| format!("Failed to sign in to {email}"))?;
+        self.cache.insert(
+            email,
+            &sign_in.bearer_token,
+            Some(&sign_in.refresh_token),
+            persist,
+        );
+        Ok(sign_in.bearer_token)
+    }
+
+    /// Signs in to the accounts of the snipe, reusing cached tokens that stay valid until
+    /// `valid_until`. Accounts that fail to sign in or can't change their name are removed
+    /// from `config`, unless there's only one.
+    pub async fn sign_in(
+        &mut self,
+        config: &mut Config,
+        valid_until: DateTime<Utc>,
+    ) -> Result<Vec<String>> {
+        let task = self.task;
+        let mut bearer_tokens = Vec::new();
+        let mut account_idx = 0;
+        let mut has_signed_in = false;
+        for account in config.account_entry.clone() {
+            let account = &account;
+            let cached = account
+                .email
+                .as_deref()
+                .and_then(|email| self.cache.get(email, valid_until))
+                .map(str::to_string);
+            if cached.is_none() && account.bearer.is_none() {
+                if has_signed_in {
+                    self.rate_limit_wait().await?;
+                }
+                has_signed_in = true;
+            }
+            let authenticated = match cached {
+                Some(token) => {
+                    (self.on_event)(&AuthEvent::CachedToken {
+                        email: account.email.as_ref().unwrap(),
+                    })?;
+                    Ok(token)
+                }
+                None => self.authenticate(account, true).await,
+            };
+            let bearer_token = match authenticated {
+                Ok(x) => x,
+                Err(y) => {
+                    if config.account_entry.len() == 1 {
+                        bail!(y);
+                    }
+                    (self.on_event)(&AuthEvent::SignInFailed { task, error: &y })?;
+                    config.account_entry.remove(account_idx);
+                    continue;
+                }
+            };
+            if account.bearer.is_none() && task != SnipeTask::Giftcode {
+                let email = account.email.as_ref().unwrap();
+                if let Err(y) = self
+                    .sniper
+                    .check_name_change_eligibility(&bearer_token)
+                    .await
+                    .with_context(|| format!("Failed to check name change eligibility of {email}"))
+                {
+                    if config.account_entry.len() == 1 {
+                        bail!(y);
+                    }
+                    (self.on_event)(&AuthEvent::NotEligible { email, error: &y })?;
+                    config.account_entry.remove(account_idx);
+                    continue;
+                }
+            }
+            bearer_tokens.push(bearer_token);
+            if task != SnipeTask::Giftcode || bearer_tokens.len() == 10 {
+                break;
+            }
+            account_idx += 1;
+        }
+        self.save()?;
+        if bearer_tokens.is_empty() {
+            bail!("No Microsoft accounts left to use");
+        }
+        Ok(bearer_tokens)
+    }
+
+    /// Warns about bearer tokens from the config that expire before `valid_until`, as there
+    /// are no credentials to sign in again with.
+    pub fn warn_expiring_bearers(
+        &self,
+        accounts: &[Account],
+        valid_until: DateTime<Utc>,
+    ) -> Result<()> {
+        for (idx, account) in accounts.iter().enumerate() {
+            let Some(bearer) = &account.bearer else {
+                continue;
+            };
+            let expires = tokens::expiry(bearer);
+            if expires.is_some_and(|expires| expires > valid_until) {
+                continue;
+            }
+            (self.on_event)(&AuthEvent::BearerExpiring {
+                label: &account.label(idx),
+                expires,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Signs in again with accounts whose tokens would expire before the snipe, shortly
+    /// before their tokens expire, until every token stays valid through the snipe or
+    /// signing in again no longer helps.
+    pub async fn refresh_expiring_tokens(
+        &mut self,
+        accounts: &[Account],
+        job: &SnipeJob,
+        bearer_tokens: &mut [String],
+    ) -> Result<()> {
+        let valid_until = job.token_deadline();
+        let connect_time = job.snipe_time() - Duration::seconds(i64::from(job.lead_time));
+        let mut has_refreshed = false;
+        loop {
+            let expiring: Vec<(usize, DateTime<Utc>)> = bearer_tokens
+                .iter()
+                .enumerate()
+                .filter(|&(idx, _)| accounts[idx].bearer.is_none())
+                .filter_map(|(idx, token)| Some((idx, tokens::expiry(token)?)))
+                .filter(|&(_, expires)| expires <= valid_until)
+                .collect();
+            let Some(earliest) = expiring.iter().map(|&(_, expires)| expires).min() else {
+                return Ok(());
+            };
+            // Each account takes up to half a minute to sign in again, including the wait
+            // between accounts, which mustn't hold up the connections of the snipe
+            let lead = constants::TOKEN_REFRESH_LEAD
+                + constants::TOKEN_REFRESH_LEAD_PER_ACCOUNT * i64::try_from(expiring.len())?;
+            let refresh_time = earliest.min(connect_time) - Duration::seconds(lead);
+            let labels: Vec<String> = expiring
+                .iter()
+                .map(|&(idx, _)| accounts[idx].label(idx))
+                .collect();
+            if has_refreshed && refresh_time <= Utc::now() {
+                return (self.on_event)(&AuthEvent::StillExpiring { labels: &labels });
+            }
+            (self.on_event)(&AuthEvent::SigningInAgain {
+                labels: &labels,
+                at: refresh_time,
+            })?;
+            if let Ok(duration) = (refresh_time - Utc::now()).to_std() {
+                sleep(duration).await;
+            }
+            for (count, &(idx, _)) in expiring.iter().enumerate() {
+                let account = &accounts[idx];
+                let label = &account.label(idx);
+                if Utc::now() + Duration::seconds(constants::TOKEN_REFRESH_LEAD_PER_ACCOUNT)
+                    >= connect_time
+                {
+                    (self.on_event)(&AuthEvent::NoTimeToSignInAgain { label })?;
+                    break;
+                }
+                if count != 0 {
+                    self.rate_limit_wait().await?;
+                }
+                // Nobody may be around to enter a device code, and waiting for one could hold
+                // up the snipe, so only refresh tokens are used
+                match self.authenticate(account, false).await {
+                    Ok(token) => bearer_tokens[idx] = token,
+                    Err(error) => (self.on_event)(&AuthEvent::SignInAgainFailed {
+                        label,
+                        error: &error,
+                    })?,
+                }
+            }
+            self.save()?;
+            has_refreshed = true;
+        }
+    }
+}
@@ -0,0 +1,506 @@
+#![warn(clippy::pedantic)]
+
+use ansi_term::Colour::{Cyan, Green, Red};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Local, TimeZone, Utc};
+use native_tls::Identity;
+use serde_json::json;
+use std::{
+    collections::HashSet,
+    io::{stdout, Write},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+use structopt::StructOpt;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+    time::{sleep, timeout},
+};
+
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+const MAX_BODY_SIZE: usize = 8 * 1024 * 1024;
+
+#[derive(StructOpt)]
+#[structopt(
+    name = "buckshot-mock",
+    author,
+    about = "Stand-in for the Minecraft services used by buckshot, for offline rehearsal"
+)]
+struct Args {
+    /// Address to listen on
+    #[structopt(short, long, default_value = "127.0.0.1:8443")]
+    listen: SocketAddr,
+
+    /// UNIX timestamp of droptime
+    #[structopt(short, long)]
+    droptime: Option<i64>,
+
+    /// Seconds from now until droptime, used when no droptime timestamp is given
+    #[structopt(long, default_value = "60")]
+    delay: i64,
+
+    /// Seconds to wait for a complete request before closing the connection
+    #[structopt(long, default_value = "120")]
+    read_timeout: u64,
+
+    /// Simulated one-way network latency in milliseconds, applied to every request and
+    /// response
+    #[structopt(long, default_value = "0")]
+    latency: u64,
+
+    /// Milliseconds the Date header is shifted by, to simulate a skewed server clock
+    #[structopt(long, default_value = "0", allow_hyphen_values = true)]
+    clock_skew: i64,
+
+    /// Report every account as being on name change cooldown
+    #[structopt(long)]
+    cooldown: bool,
+
+    /// Seconds until issued bearer tokens expire
+    #[structopt(long, default_value = "86400")]
+    token_lifetime: i64,
+}
+
+struct State {
+    droptime: DateTime<Local>,
+    clock_skew: Duration,
+    cooldown: bool,
+    token_lifetime: Duration,
+    claimed: Mutex<HashSet<String>>,
+    device_code_polls: Mutex<u32>,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    host: String,
+    body: Vec<u8>,
+    received: DateTime<Local>,
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl Response {
+    fn json(status: u16, body: &serde_json::Value) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type", "application/json".to_string())],
+            body: body.to_string(),
+        }
+    }
+
+    fn html(body: String) -> Self {
+        Self {
+            status: 200,
+            headers: vec![("Content-Type", "text/html".to_string())],
+            body,
+        }
+    }
+
+    fn redirect(location: String) -> Self {
+        Self {
+            status: 302,
+            headers: vec![("Location", location)],
+            body: String::new(),
+        }
+    }
+
+    fn error(status: u16, path: &str, error: &str, message: &str) -> Self {
+        Self::json(
+            status,
+            &json!({
+                "path": path,
+                "errorType": error,
+                "error": error,
+                "errorMessage": message,
+                "developerMessage": message
+            }),
+        )
+    }
+
+    fn to_bytes(&self, clock_skew: Duration) -> Vec<u8> {
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nDate: {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+            self.status,
+            reason_phrase(self.status),
+            (Utc::now() + clock_skew).format("%a, %d %b %Y %H:%M:%S GMT"),
+            self.body.len()
+        );
+        for (name, value) in &self.headers {
+            head.extend([name, ": ", value, "\r\n"]);
+        }
+        head.push_str("\r\n");
+        let mut bytes = head.into_bytes();
+        bytes.extend_from_slice(self.body.as_bytes());
+        bytes
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::from_args();
+    // The droptime API only has a resolution of one second, so the droptime is a whole
+    // second for the advertised droptime to be the one that is enforced
+    let timestamp = args
+        .droptime
+        .unwrap_or_else(|| Local::now().timestamp() + args.delay);
+    let droptime = Local.timestamp(timestamp, 0);
+    let cert = rcgen::generate_simple_self_signed(vec![
+        "localhost".to_string(),
+        args.listen.ip().to_string(),
+    ])
+    .with_context(|| "Failed to generate a self-signed certificate")?;
+    let identity = Identity::from_pkcs8(
+        cert.cert.pem().as_bytes(),
+        cert.key_pair.serialize_pem().as_bytes(),
+    )?;
+    let acceptor = native_tls::TlsAcceptor::new(identity)?;
+    let acceptor = Arc::new(tokio_native_tls::TlsAcceptor::from(acceptor));
+    let listener = TcpListener::bind(args.listen)
+        .await
+        .with_context(|| format!("Failed to listen on {}", args.listen))?;
+    let state = Arc::new(State {
+        droptime,
+        clock_skew: Duration::milliseconds(args.clock_skew),
+        cooldown: args.cooldown,
+        token_lifetime: Duration::seconds(args.token_lifetime),
+        claimed: Mutex::new(HashSet::new()),
+        device_code_polls: Mutex::new(0),
+    });
+    let read_timeout = std::time::Duration::from_secs(args.read_timeout);
+    let latency = std::time::Duration::from_millis(args.latency);
+    let base_url = format!("https://{}", args.listen);
+    writeln!(
+        stdout(),
+        "Listening on {} with droptime {} ({})",
+        base_url,
+        droptime.format("%F %T"),
+        droptime.timestamp()
+    )?;
+    writeln!(
+        stdout(),
+        "Point buckshot at this server with the following config:\n\n[endpoints]"
+    )?;
+    for endpoint in [
+        "minecraft_services",
+        "mojang_auth",
+        "mojang_api",
+        "droptime",
+        "live_login",
+        "xbl",
+        "xsts",
+    ] {
+        writeln!(stdout(), "{endpoint} = \"{base_url}\"")?;
+    }
+    writeln!(stdout(), "accept_invalid_certs = true\n")?;
+    loop {
+        let (socket, peer) = listener.accept().await?;
+        socket.set_nodelay(true)?;
+        let acceptor = Arc::clone(&acceptor);
+        let state = Arc::clone(&state);
+        tokio::task::spawn(async move {
+            let result = async {
+                let socket = acceptor.accept(socket).await?;
+                let (reader, mut writer) = tokio::io::split(socket);
+                let mut request = timeout(read_timeout, read_request(BufReader::new(reader)))
+                    .await
+                    .with_context(|| "Timed out reading request")??;
+                if !latency.is_zero() {
+                    sleep(latency).await;
+                    request.received = Local::now();
+                }
+                let response = route(&state, &request);
+                log_request(&state, &request, &response, peer)?;
+                sleep(latency).await;
+                writer
+                    .write_all(&response.to_bytes(state.clock_skew))
+                    .await?;
+                writer.shutdown().await?;
+                Ok::<(), anyhow::Error>(())
+            }
+            .await;
+            if let Err(error) = result {
+                let _ = writeln!(
+                    stdout(),
+                    "{}",
+                    Red.paint(format!("Connection from {peer} failed: {error:#}"))
+                );
+            }
+        });
+    }
+}
+
+async fn read_request<R: AsyncRead + Unpin>(mut reader: BufReader<R>) -> Result<Request> {
+    let mut head_size = 0;
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        let read = reader.read_line(&mut line).await?;
+        if read == 0 {
+            bail!("Connection closed before the request was complete");
+        }
+        head_size += read;
+        if head_size > MAX_HEAD_SIZE {
+            bail!("Request head too large");
+        }
+        let line = line.trim_end_matches(['\r', '\n']).to_string();
+        if line.is_empty() {
+            break;
+        }
+        lines.push(line);
+    }
+    let mut lines = lines.into_iter();
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split(' ');
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => bail!("Malformed request line: {request_line}"),
+    };
+    let mut host = String::new();
+    let mut content_length = 0;
+    let mut chunked = false;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            bail!("Malformed header: {line}");
+        };
+        let value = value.trim();
+        match name.to_ascii_lowercase().as_str() {
+            "host" => host = value.to_string(),
+            "content-length" => content_length = value.parse()?,
+            "transfer-encoding" => chunked = value.eq_ignore_ascii_case("chunked"),
+            _ => {}
+        }
+    }
+    let mut body = Vec::new();
+    if chunked {
+        loop {
+            let mut size_line = String::new();
+            reader.read_line(&mut size_line).await?;
+            let size_hex = size_line.trim().split(';').next().unwrap_or_default();
+            let size = usize::from_str_radix(size_hex, 16)?;
+            if size == 0 {
+                let mut trailer = String::new();
+                reader.read_line(&mut trailer).await?;
+                break;
+            }
+            if body.len() + size > MAX_BODY_SIZE {
+                bail!("Request body too large");
+            }
+            let mut chunk = vec![0; size + 2];
+            reader.read_exact(&mut chunk).await?;
+            body.extend_from_slice(&chunk[..size]);
+        }
+    } else {
+        if content_length > MAX_BODY_SIZE {
+            bail!("Request body too large");
+        }
+        body.resize(content_length, 0);
+        reader.read_exact(&mut body).await?;
+    }
+    Ok(Request {
+        method,
+        path,
+        host,
+        body,
+        received: Local::now(),
+    })
+}
+
+fn route(state: &State, request: &Request) -> Response {
+    let path = request.path.split('?').next().unwrap_or_default();
+    match (request.method.as_str(), path) {
+        ("PUT", path) if path.starts_with("/minecraft/profile/name/") => {
+            let name = &path["/minecraft/profile/name/".len()..];
+            claim_name(state, request, name)
+        }
+        ("POST", "/minecraft/profile") => {
+            let name = serde_json::from_slice::<serde_json::Value>(&request.body)
+                .ok()
+                .and_then(|body| body["profileName"].as_str().map(ToString::to_string));
+            match name {
+                Some(name) => claim_name(state, request, &name),
+                None => Response::error(400, path, "BAD_REQUEST", "Missing profileName"),
+            }
+        }
+        ("GET", "/minecraft/profile/namechange") => Response::json(
+            200,
+            &json!({
+                "changedAt": "2020-01-01T00:00:00Z",
+                "createdAt": "2020-01-01T00:00:00Z",
+                "nameChangeAllowed": !state.cooldown
+            }),
+        ),
+        ("POST", "/minecraft/profile/skins") => Response::json(200, &json!({})),
+        ("POST", "/authentication/login_with_xbox") => Response::json(
+            200,
+            &json!({
+                "username": "mock",
+                "access_token": bearer_token(state),
+                "token_type": "Bearer",
+                "expires_in": state.token_lifetime.num_seconds()
+            }),
+        ),
+        ("POST", "/authenticate") => {
+            Response::json(200, &json!({ "accessToken": bearer_token(state) }))
+        }
+        ("GET", "/user/security/challenges") => Response::json(200, &json!([])),
+        ("POST", "/user/security/location") => Response {
+            status: 204,
+            headers: Vec::new(),
+            body: String::new(),
+        },
+        ("GET", "/oauth20_authorize.srf") => Response::html(format!(
+            r#"<input type="hidden" name="PPFT" value="mock-ppft"/><script>var ServerData = {{urlPost:'https://{}/ppsecure/post.srf'}};</script>"#,
+            request.host
+        )),
+        ("POST", "/ppsecure/post.srf") => Response::redirect(format!(
+            "https://{}/oauth20_desktop.srf#access_token=mock-live-token&token_type=bearer&expires_in=86400",
+            request.host
+        )),
+        ("GET", "/oauth20_desktop.srf") => Response::html(String::new()),
+        ("POST", "/oauth20_connect.srf") => Response::json(
+            200,
+            &json!({
+                "user_code": "MOCKCODE",
+                "device_code": "mock-device-code",
+                "verification_uri": format!("https://{}/link", request.host),
+                "expires_in": 900,
+                "interval": 1
+            }),
+        ),
+        ("POST", "/oauth20_token.srf") => device_code_token(state, request),
+        ("POST", "/user/authenticate" | "/xsts/authorize") => Response::json(
+            200,
+            &json!({
+                "Token": "mock-xbox-token",
+                "DisplayClaims": { "xui": [{ "uhs": "mock-uhs" }] }
+            }),
+        ),
+        ("GET", path) if path.starts_with("/droptime/") => {
+            let name = &path["/droptime/".len()..];
+            Response::json(
+                200,
+                &json!({ "username": name, "unix": state.droptime.timestamp() }),
+            )
+        }
+        _ => Response::error(404, path, "NOT_FOUND", "The server has not found anything matching the request URI"),
+    }
+}
+
+/// Answers every other device code poll as if the code had been entered in between, and
+/// accepts every refresh token.
+fn device_code_token(state: &State, request: &Request) -> Response {
+    if String::from_utf8_lossy(&request.body).contains("grant_type=urn") {
+        let mut polls = state.device_code_polls.lock().unwrap();
+        *polls += 1;
+        if *polls % 2 == 1 {
+            return Response::json(
+                400,
+                &json!({
+                    "error": "authorization_pending",
+                    "error_description": "The user hasn't entered the code yet."
+                }),
+            );
+        }
+    }
+    Response::json(
+        200,
+        &json!({
+            "token_type": "bearer",
+            "expires_in": 86400,
+            "scope": "service::user.auth.xboxlive.com::MBI_SSL",
+            "access_token": "mock-live-token",
+            "refresh_token": "mock-refresh-token",
+            "user_id": "mock"
+        }),
+    )
+}
+
+/// Issues an unsigned JWT that expires after the configured token lifetime.
+fn bearer_token(state: &State) -> String {
+    let encode = |value: serde_json::Value| {
+        base64::encode_config(value.to_string(), base64::URL_SAFE_NO_PAD)
+    };
+    let expires = Utc::now() + state.token_lifetime;
+    format!(
+        "{}.{}.mock-signature",
+        encode(json!({ "alg": "none", "typ": "JWT" })),
+        encode(json!({ "sub": "mock", "exp": expires.timestamp() }))
+    )
+}
+
+fn claim_name(state: &State, request: &Request, name: &str) -> Response {
+    if request.received < state.droptime {
+        return Response::error(
+            403,
+            &request.path,
+            "FORBIDDEN",
+            "Could not change name for profile",
+        );
+    }
+    let mut claimed = state.claimed.lock().unwrap();
+    if !claimed.insert(name.to_ascii_lowercase()) {
+        return Response::json(
+            403,
+            &json!({
+                "path": request.path,
+                "details": { "status": "DUPLICATE" },
+                "errorMessage": "Name is already taken"
+            }),
+        );
+    }
+    Response::json(
+        200,
+        &json!({
+            "id": "00000000000000000000000000000000",
+            "name": name,
+            "skins": [],
+            "capes": []
+        }),
+    )
+}
+
+fn log_request(
+    state: &State,
+    request: &Request,
+    response: &Response,
+    peer: SocketAddr,
+) -> Result<()> {
+    let relative = (request.received - state.droptime)
+        .num_microseconds()
+        .unwrap_or(i64::MAX);
+    #[allow(clippy::cast_precision_loss)]
+    let relative = format!("{:+.3} ms", relative as f64 / 1000.0);
+    let status = if response.status < 400 {
+        Green.paint(response.status.to_string())
+    } else {
+        Red.paint(response.status.to_string())
+    };
+    writeln!(
+        stdout(),
+        "[{}] {} {} from {} @ {}",
+        status,
+        request.method,
+        request.path,
+        peer,
+        Cyan.paint(relative)
+    )?;
+    Ok(())
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        302 => "Found",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        _ => "Unknown",
+    }
+}
@@ -0,0 +1,208 @@
+use crate::{config::Endpoints, constants::RESPONSE_TIMEOUT, sockets::Target};
+use anyhow::{Context, Result};
+use std::time::{Duration, Instant};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Timings of a single connection made the same way as a snipe request.
+pub struct Sample {
+    pub connect: Duration,
+    pub handshake: Duration,
+    pub round_trip: Duration,
+}
+
+/// Summary of a timing distribution in milliseconds.
+pub struct Stats {
+    pub min: f64,
+    pub median: f64,
+    pub mean: f64,
+    pub max: f64,
+    pub stddev: f64,
+}
+
+pub struct Calibration {
+    pub samples: usize,
+    pub connect: Stats,
+    pub handshake: Stats,
+    pub round_trip: Stats,
+    /// Recommended offset in milliseconds.
+    pub offset: u32,
+    /// 95% confidence interval of the recommended offset in milliseconds.
+    pub interval: (f64, f64),
+}
+
+/// Opens `count` connections to the Minecraft services host one after another and times
+/// the TCP connect, the TLS handshake and the round trip of a small request.
+pub async fn measure(endpoints: &Endpoints, count: usize) -> Result<Vec<Sample>> {
+    let target = Target::new(endpoints)?;
+    let request = format!(
+        "GET {}/minecraft/profile/namechange HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        target.base_path, target.host
+    );
+    let mut samples = Vec::with_capacity(count);
+    for idx in 0..count {
+        if idx != 0 {
+            tokio::time::sleep(SAMPLE_INTERVAL).await;
+        }
+        let start = Instant::now();
+        let socket = TcpStream::connect(target.addr).await.with_context(|| {
+            format!("Failed to establish a TCP connection with {}", target.host)
+        })?;
+        let connected = Instant::now();
+        let mut socket = target
+            .connector
+            .connect(&target.host, socket)
+            .await
+            .with_context(|| format!("Failed to initiate a TLS handshake with {}", target.host))?;
+        let handshaken = Instant::now();
+        socket.write_all(request.as_bytes()).await?;
+        let sent = Instant::now();
+        timeout(RESPONSE_TIMEOUT, socket.read(&mut [0; 1]))
+            .await
+            .with_context(|| "Timed out waiting for a response")??;
+        let responded = Instant::now();
+        samples.push(Sample {
+            connect: connected - start,
+            handshake: handshaken - connected,
+            round_trip: responded - sent,
+        });
+    }
+    Ok(samples)
+}
+
+/// Recommends an offset of half the median request round trip, i.e. the estimated time
+/// between a request leaving this machine and the server receiving it. The confidence
+/// interval is a distribution-free interval for the median built from order statistics.
+#[must_use]
+pub fn recommend(samples: &[Sample]) -> Calibration {
+    let connect = to_millis(samples, |sample| sample.connect);
+    let handshake = to_millis(samples, |sample| sample.handshake);
+    let round_trip = to_millis(samples, |sample| sample.round_trip);
+    let n = round_trip.len();
+    #[allow(clippy::cast_precision_loss)]
+    let spread = 1.96 * (n as f64).sqrt() / 2.0;
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    let (lower, upper) = {
+        // 1-based rank of the lower bound, with the upper bound as far above the median
+        let rank = (n as f64 / 2.0 - spread).floor().max(1.0) as usize;
+        (round_trip[rank - 1] / 2.0, round_trip[n - rank] / 2.0)
+    };
+    let round_trip = stats(&round_trip);
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let offset = (round_trip.median / 2.0).round() as u32;
+    Calibration {
+        samples: n,
+        connect: stats(&connect),
+        handshake: stats(&handshake),
+        round_trip,
+        offset,
+        interval: (lower, upper),
+    }
+}
+
+fn to_millis(samples: &[Sample], field: impl Fn(&Sample) -> Duration) -> Vec<f64> {
+    let mut values: Vec<f64> = samples
+        .iter()
+        .map(|sample| field(sample).as_secs_f64() * 1000.0)
+        .collect();
+    values.sort_unstable_by(f64::total_cmp);
+    values
+}
+
+/// Expects `values` to be sorted and non-empty.
+fn stats(values: &[f64]) -> Stats {
+    let n = values.len();
+    #[allow(clippy::cast_precision_loss)]
+    let mean = values.iter().sum::<f64>() / n as f64;
+    #[allow(clippy::cast_precision_loss)]
+    let variance = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n as f64;
+    let median = if n.is_multiple_of(2) {
+        f64::midpoint(values[n / 2 - 1], values[n / 2])
+    } else {
+        values[n / 2]
+    };
+    Stats {
+        min: values[0],
+        median,
+        mean,
+        max: values[n - 1],
+        stddev: variance.sqrt(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(round_trips: &[u64]) -> Vec<Sample> {
+        round_trips
+            .iter()
+            .map(|&round_trip| Sample {
+                connect: Duration::from_millis(1),
+                handshake: Duration::from_millis(2),
+                round_trip: Duration::from_millis(round_trip),
+            })
+            .collect()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn odd_sample_count() {
+        let calibration = recommend(&samples(&[90, 10, 50, 30, 70, 20, 80, 40, 60]));
+        assert_eq!(calibration.samples, 9);
+        assert_close(calibration.round_trip.min, 10.0);
+        assert_close(calibration.round_trip.max, 90.0);
+        assert_close(calibration.round_trip.median, 50.0);
+        assert_close(calibration.round_trip.mean, 50.0);
+        assert_close(calibration.round_trip.stddev, (6000.0_f64 / 9.0).sqrt());
+        assert_close(calibration.connect.median, 1.0);
+        assert_close(calibration.handshake.median, 2.0);
+        assert_eq!(calibration.offset, 25);
+        assert_close(calibration.interval.0, 5.0);
+        assert_close(calibration.interval.1, 45.0);
+    }
+
+    #[test]
+    fn even_sample_count() {
+        let calibration = recommend(&samples(&[40, 10, 30, 20]));
+        assert_close(calibration.round_trip.median, 25.0);
+        assert_eq!(calibration.offset, 13);
+        assert_close(calibration.interval.0, 5.0);
+        assert_close(calibration.interval.1, 20.0);
+    }
+
+    #[test]
+    fn interval_is_symmetric_around_the_median() {
+        let round_trips: Vec<u64> = (1..=100).rev().collect();
+        let calibration = recommend(&samples(&round_trips));
+        assert_close(calibration.round_trip.median, 50.5);
+        assert_eq!(calibration.offset, 25);
+        // Ranks 40 and 61 of 100, halved
+        assert_close(calibration.interval.0, 20.0);
+        assert_close(calibration.interval.1, 30.5);
+    }
+
+    #[test]
+    fn single_sample() {
+        let calibration = recommend(&samples(&[33]));
+        assert_eq!(calibration.offset, 17);
+        assert_close(calibration.interval.0, 16.5);
+        assert_close(calibration.interval.1, 16.5);
+        assert_close(calibration.round_trip.stddev, 0.0);
+    }
+}
@@ -1,5 +1,10 @@
 use anyhow::Result;
-use dialoguer::Input;
+use buckshot::{config::Endpoints, constants, vault::VaultAccount};
+use dialoguer::{Input, Password};
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
@@ -12,12 +17,155 @@ pub struct Args {
     /// UNIX timestamp of droptime
     #[structopt(short, long, requires = "name")]
     pub timestamp: Option<i64>,
+
+    /// Override the Minecraft services API base URL
+    #[structopt(long)]
+    pub minecraft_services_url: Option<String>,
+
+    /// Override the Mojang authentication server base URL
+    #[structopt(long)]
+    pub mojang_auth_url: Option<String>,
+
+    /// Override the Mojang API base URL
+    #[structopt(long)]
+    pub mojang_api_url: Option<String>,
+
+    /// Override the droptime API base URL
+    #[structopt(long)]
+    pub droptime_url: Option<String>,
+
+    /// Override the Microsoft login base URL
+    #[structopt(long)]
+    pub live_login_url: Option<String>,
+
+    /// Override the Xbox Live user authentication base URL
+    #[structopt(long)]
+    pub xbl_url: Option<String>,
+
+    /// Override the Xbox Live XSTS authorization base URL
+    #[structopt(long)]
+    pub xsts_url: Option<String>,
+
+    /// Path to the config file
+    #[structopt(
+        short,
+        long,
+        env = "BUCKSHOT_CONFIG",
+        default_value = constants::CONFIG_PATH,
+        parse(from_os_str)
+    )]
+    pub config: PathBuf,
+
+    /// Path to the account vault [default: buckshot.vault next to the config file]
+    #[structopt(long, env = "BUCKSHOT_VAULT", parse(from_os_str))]
+    pub vault: Option<PathBuf>,
+
+    /// Accept invalid TLS certificates, e.g. from a local mock server
+    #[structopt(long)]
+    pub accept_invalid_certs: bool,
+
+    /// Print the request schedule without signing in or sending anything
+    #[structopt(long)]
+    pub dry_run: bool,
+
+    /// Print the dry run schedule as JSON
+    #[structopt(long, requires = "dry-run")]
+    pub json: bool,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(StructOpt)]
+pub enum Command {
+    /// Measure connection timings to the Minecraft services API and recommend an offset
+    Calibrate {
+        /// Number of connections to measure
+        #[structopt(short, long, default_value = "20")]
+        samples: usize,
+
+        /// Write the recommended offset to the config file
+        #[structopt(short, long)]
+        write: bool,
+    },
+    /// Check the config, accounts, skin and droptimes without sniping
+    Check,
+    /// Manage the encrypted account vault
+    Vault(VaultCommand),
+    /// Analyse the snipe history and recommend an offset
+    Stats {
+        /// Only analyse the most recent snipes
+        #[structopt(short, long)]
+        last: Option<usize>,
+        /// Include snipes sent to other Minecraft services endpoints than the configured
+        /// one, such as rehearsals against buckshot-mock
+        #[structopt(long)]
+        all_endpoints: bool,
+    },
+}
+
+#[derive(StructOpt)]
+pub enum VaultCommand {
+    /// Add an account to the vault, prompting for its credentials
+    Add {
+        /// Label that config entries refer to the account by
+        label: String,
+
+        /// Store a bearer token instead of an email and password
+        #[structopt(long)]
+        bearer: bool,
+
+        /// Also store answers to the security questions of a Mojang account
+        #[structopt(long, conflicts_with = "bearer")]
+        sq: bool,
+
+        /// Only store the email of a Microsoft account that signs in with a device code
+        #[structopt(long, conflicts_with_all = &["bearer", "sq"])]
+        device_code: bool,
+    },
+    /// List the accounts in the vault
+    List,
+    /// Remove an account from the vault
+    Remove { label: String },
+    /// Print every account in the vault as a plaintext account entry
+    Export,
 }
 
 impl Args {
     pub fn new() -> Self {
         Self::from_args()
     }
+
+    pub fn vault_path(&self) -> PathBuf {
+        self.vault.clone().unwrap_or_else(|| {
+            self.config
+                .parent()
+                .unwrap_or_else(|| Path::new(""))
+                .join(constants::VAULT_PATH)
+        })
+    }
+
+    pub fn override_endpoints(&self, endpoints: &mut Endpoints) {
+        for (url, endpoint) in [
+            (
+                &self.minecraft_services_url,
+                &mut endpoints.minecraft_services,
+            ),
+            (&self.mojang_auth_url, &mut endpoints.mojang_auth),
+            (&self.mojang_api_url, &mut endpoints.mojang_api),
+            (&self.droptime_url, &mut endpoints.droptime),
+            (&self.live_login_url, &mut endpoints.live_login),
+            (&self.xbl_url, &mut endpoints.xbl),
+            (&self.xsts_url, &mut endpoints.xsts),
+        ] {
+            if let Some(url) = url {
+                endpoint.clone_from(url);
+            }
+        }
+        if self.accept_invalid_certs {
+            endpoints.accept_invalid_certs = true;
+        }
+    }
 }
 
 pub fn get_name_choice() -> Result<String> {
@@ -25,3 +173,50 @@ pub fn get_name_choice() -> Result<String> {
         .with_prompt("What name would you like to snipe")
         .interact()?)
 }
+
+/// Takes the vault passphrase from `BUCKSHOT_VAULT_PASSPHRASE` or prompts for it, asking
+/// twice if a new vault is being created.
+pub fn get_vault_passphrase(is_new: bool) -> Result<String> {
+    if let Ok(passphrase) = env::var("BUCKSHOT_VAULT_PASSPHRASE") {
+        return Ok(passphrase);
+    }
+    let mut prompt = Password::new();
+    prompt.with_prompt("Vault passphrase");
+    if is_new {
+        prompt.with_confirmation("Confirm passphrase", "Passphrases don't match");
+    }
+    Ok(prompt.interact()?)
+}
+
+pub fn get_vault_account(
+    label: String,
+    bearer: bool,
+    sq: bool,
+    device_code: bool,
+) -> Result<VaultAccount> {
+    let mut account = VaultAccount {
+        label,
+        email: None,
+        password: None,
+        sq_ans: None,
+        bearer: None,
+    };
+    if bearer {
+        account.bearer = Some(Password::new().with_prompt("Bearer token").interact()?);
+        return Ok(account);
+    }
+    account.email = Some(Input::new().with_prompt("Email").interact()?);
+    if device_code {
+        return Ok(account);
+    }
+    account.password = Some(Password::new().with_prompt("Password").interact()?);
+    if sq {
+        let answer = |idx: usize| -> Result<String> {
+            Ok(Password::new()
+                .with_prompt(format!("Security question answer #{idx}"))
+                .interact()?)
+        };
+        account.sq_ans = Some([answer(1)?, answer(2)?, answer(3)?]);
+    }
+    Ok(account)
+}
@@ -0,0 +1,282 @@
+use crate::{
+    config::{ClockCheck, ClockSource, Endpoints},
+    constants::RESPONSE_TIMEOUT,
+    http,
+    sockets::Target,
+};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use std::{fmt, time::Instant};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpStream, UdpSocket},
+    time::{sleep, sleep_until, timeout},
+};
+
+const NTP_UNIX_EPOCH_DELTA: i64 = 2_208_988_800;
+const DATE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+const DATE_POLL_COUNT: usize = 13;
+const DATE_BISECTIONS: usize = 8;
+/// Milliseconds left to connect to the server before a bisection probe is sent.
+const DATE_CONNECT_LEAD: i64 = 500;
+
+/// Difference between a reference clock and the local clock.
+pub struct ClockSkew {
+    /// Reference time minus local time. Positive when the local clock is behind.
+    pub skew: Duration,
+    /// How far the true skew may be from `skew` in either direction.
+    pub uncertainty: Duration,
+    pub source: ClockSource,
+}
+
+impl fmt::Display for ClockSkew {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = if self.skew < Duration::zero() {
+            "ahead of"
+        } else {
+            "behind"
+        };
+        let source = match self.source {
+            ClockSource::Http => "the server's Date header",
+            ClockSource::Ntp => "NTP",
+        };
+        write!(
+            f,
+            "Local clock is {:.1} ms {direction} the reference (±{:.1} ms, via {source})",
+            to_millis(self.skew).abs(),
+            to_millis(self.uncertainty)
+        )
+    }
+}
+
+pub async fn measure(clock_check: &ClockCheck, endpoints: &Endpoints) -> Result<ClockSkew> {
+    match clock_check.source {
+        ClockSource::Http => http_date(endpoints)
+            .await
+            .with_context(|| "Failed to compare the local clock against the server's Date header"),
+        ClockSource::Ntp => sntp(&clock_check.ntp_server)
+            .await
+            .with_context(|| format!("Failed to query the NTP server {}", clock_check.ntp_server)),
+    }
+}
+
+/// The `Date` header only has a resolution of one second, so the server is polled until
+/// the header ticks over to the next second. Because the header ticks over at the same
+/// point of every second, the tick is then narrowed down by bisection, probing the middle
+/// of the remaining window one second later each time. The server stamps each response
+/// somewhere between the request being sent and the response arriving, so the tick is
+/// known to within half a round trip at best.
+async fn http_date(endpoints: &Endpoints) -> Result<ClockSkew> {
+    let target = Target::new(endpoints)?;
+    let anchor = Anchor::capture();
+    let mut previous: Option<DateSample> = None;
+    let mut window = None;
+    for idx in 0..DATE_POLL_COUNT {
+        if idx != 0 {
+            sleep(DATE_POLL_INTERVAL).await;
+        }
+        let sample = date_sample(&target, &anchor, None).await?;
+        if let Some(previous) = &previous {
+            if sample.server > previous.server {
+                window = Some(TickWindow {
+                    second: sample.server,
+                    earliest: previous.sent,
+                    latest: sample.received,
+                });
+                break;
+            }
+        }
+        previous = Some(sample);
+    }
+    let Some(mut window) = window else {
+        // The Date header never ticked over, so all that is known is which second it is
+        let previous = previous.unwrap();
+        return Ok(ClockSkew {
+            skew: Utc.timestamp(previous.server, 0) + Duration::milliseconds(500)
+                - previous.midpoint(),
+            uncertainty: Duration::milliseconds(500) + previous.round_trip() / 2,
+            source: ClockSource::Http,
+        });
+    };
+    let mut round_trip = Duration::zero();
+    let mut seconds = 0;
+    for _ in 0..DATE_BISECTIONS {
+        if window.latest - window.earliest <= round_trip + Duration::milliseconds(1) {
+            break;
+        }
+        let middle = window.earliest + (window.latest - window.earliest) / 2;
+        let send_time = loop {
+            seconds += 1;
+            let send_time = middle + Duration::seconds(seconds) - round_trip / 2;
+            if send_time - anchor.now() > Duration::milliseconds(DATE_CONNECT_LEAD) {
+                break send_time;
+            }
+        };
+        let sample = date_sample(&target, &anchor, Some(send_time)).await?;
+        round_trip = sample.round_trip();
+        let shift = Duration::seconds(seconds);
+        if sample.server >= window.second + seconds {
+            window.latest = window.latest.min(sample.received - shift);
+        } else {
+            window.earliest = window.earliest.max(sample.sent - shift);
+        }
+    }
+    let half_width = (window.latest - window.earliest) / 2;
+    Ok(ClockSkew {
+        skew: Utc.timestamp(window.second, 0) - (window.earliest + half_width),
+        uncertainty: half_width,
+        source: ClockSource::Http,
+    })
+}
+
+/// Local times between which the server's `Date` header ticked over to `second`.
+struct TickWindow {
+    second: i64,
+    earliest: DateTime<Utc>,
+    latest: DateTime<Utc>,
+}
+
+/// A request for the server's `Date` header, timed on the local clock.
+struct DateSample {
+    sent: DateTime<Utc>,
+    received: DateTime<Utc>,
+    server: i64,
+}
+
+impl DateSample {
+    fn round_trip(&self) -> Duration {
+        self.received - self.sent
+    }
+
+    fn midpoint(&self) -> DateTime<Utc> {
+        self.sent + self.round_trip() / 2
+    }
+}
+
+/// Requests the second in the server's `Date` header, sending the request at `send_time`
+/// once connected or right away if it is `None`.
+async fn date_sample(
+    target: &Target,
+    anchor: &Anchor,
+    send_time: Option<DateTime<Utc>>,
+) -> Result<DateSample> {
+    let socket = TcpStream::connect(target.addr).await?;
+    let mut socket = target.connector.connect(&target.host, socket).await?;
+    let request = format!(
+        "GET {}/minecraft/profile/namechange HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        target.base_path, target.host
+    );
+    if let Some(send_time) = send_time {
+        sleep_until(anchor.instant(send_time).into()).await;
+    }
+    let sent = anchor.now();
+    socket.write_all(request.as_bytes()).await?;
+    let response = timeout(RESPONSE_TIMEOUT, http::read_response(&mut socket, anchor))
+        .await
+        .with_context(|| "Timed out waiting for a response")??;
+    let date = response
+        .header("Date")
+        .with_context(|| "Response has no Date header")?;
+    let server = DateTime::parse_from_rfc2822(date)
+        .with_context(|| format!("Malformed Date header: {date}"))?
+        .timestamp();
+    Ok(DateSample {
+        sent,
+        received: response.received,
+        server,
+    })
+}
+
+async fn sntp(server: &str) -> Result<ClockSkew> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(server).await?;
+    let mut packet = [0; 48];
+    // LI = 0, VN = 4, Mode = 3 (client)
+    packet[0] = 0x23;
+    let originate = Utc::now();
+    packet[40..48].copy_from_slice(&to_ntp(originate));
+    socket.send(&packet).await?;
+    let mut response = [0; 48];
+    let read = timeout(RESPONSE_TIMEOUT, socket.recv(&mut response))
+        .await
+        .with_context(|| "Timed out waiting for a response")??;
+    let destination = Utc::now();
+    if read < 48 {
+        bail!("NTP response too short");
+    }
+    if response[1] == 0 {
+        bail!("NTP server sent a kiss-of-death packet");
+    }
+    let receive = from_ntp(&response[32..40]);
+    let transmit = from_ntp(&response[40..48]);
+    let skew = ((receive - originate) + (transmit - destination)) / 2;
+    let delay = (destination - originate) - (transmit - receive);
+    Ok(ClockSkew {
+        skew,
+        uncertainty: delay / 2,
+        source: ClockSource::Ntp,
+    })
+}
+
+fn to_ntp(time: DateTime<Utc>) -> [u8; 8] {
+    let seconds = time.timestamp() + NTP_UNIX_EPOCH_DELTA;
+    let fraction = (u64::from(time.timestamp_subsec_nanos()) << 32) / 1_000_000_000;
+    let mut bytes = [0; 8];
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    bytes[..4].copy_from_slice(&(seconds as u32).to_be_bytes());
+    #[allow(clippy::cast_possible_truncation)]
+    bytes[4..].copy_from_slice(&(fraction as u32).to_be_bytes());
+    bytes
+}
+
+fn from_ntp(bytes: &[u8]) -> DateTime<Utc> {
+    let seconds = u32::from_be_bytes(bytes[..4].try_into().unwrap());
+    let fraction = u32::from_be_bytes(bytes[4..].try_into().unwrap());
+    #[allow(clippy::cast_possible_truncation)]
+    let nanos = ((u64::from(fraction) * 1_000_000_000) >> 32) as u32;
+    Utc.timestamp(i64::from(seconds) - NTP_UNIX_EPOCH_DELTA, nanos)
+}
+
+/// Ties the wall clock to the monotonic clock at one instant, so that waits and timestamps
+/// taken afterwards are unaffected by the system clock being stepped or slewed.
+#[derive(Clone, Copy)]
+pub struct Anchor {
+    instant: Instant,
+    time: DateTime<Utc>,
+}
+
+impl Anchor {
+    #[must_use]
+    pub fn capture() -> Self {
+        Self {
+            instant: Instant::now(),
+            time: Utc::now(),
+        }
+    }
+
+    /// The current time according to the monotonic clock.
+    #[must_use]
+    pub fn now(&self) -> DateTime<Utc> {
+        self.time + Duration::from_std(self.instant.elapsed()).unwrap_or_else(|_| Duration::zero())
+    }
+
+    /// The monotonic instant `time` corresponds to.
+    #[must_use]
+    pub fn instant(&self, time: DateTime<Utc>) -> Instant {
+        let offset = time - self.time;
+        match offset.to_std() {
+            Ok(offset) => self.instant + offset,
+            Err(_) => self
+                .instant
+                .checked_sub((-offset).to_std().unwrap_or_default())
+                .unwrap_or(self.instant),
+        }
+    }
+}
+
+#[must_use]
+pub fn to_millis(duration: Duration) -> f64 {
+    #[allow(clippy::cast_precision_loss)]
+    let micros = duration.num_microseconds().unwrap_or(i64::MAX) as f64;
+    micros / 1000.0
+}
@@ -1,21 +1,43 @@
-use crate::constants;
-use anyhow::{bail, Result};
-use serde::{de::Error, Deserialize, Deserializer};
-use std::{convert::From, fs::read_to_string};
+use crate::{constants, scheduler, vault::Vault};
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use reqwest::Url;
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
+use std::{
+    convert::From,
+    env, fmt,
+    fs::{self, read_to_string, write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::LazyLock,
+};
 
 #[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
 struct PrivateConfig {
-    account_entry: Vec<AccountVariants>,
+    account_entry: Vec<AccountEntry>,
     offset: u32,
     #[serde(deserialize_with = "to_task")]
     mode: SnipeTask,
     skin: Option<Skin>,
     name_queue: Option<NameQueue>,
+    #[serde(default, deserialize_with = "to_spread")]
+    spread: Spread,
+    #[serde(default)]
+    spin_wait: u32,
+    #[serde(default = "default_lead_time")]
+    lead_time: u32,
+    #[serde(default = "default_history_file")]
+    history_file: String,
+    #[serde(default = "default_token_cache")]
+    token_cache: String,
     #[serde(default)]
-    spread: u32,
+    endpoints: Endpoints,
+    clock_check: Option<ClockCheck>,
 }
 
 #[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct NameQueue {
     pub queue: Vec<String>,
     pub never_stop_sniping: bool,
@@ -29,50 +51,231 @@ pub struct Config {
     pub mode: SnipeTask,
     pub skin: Option<Skin>,
     pub name_queue: Option<NameQueue>,
-    pub spread: u32,
+    pub spread: Spread,
+    pub spin_wait: u32,
+    pub lead_time: u32,
+    pub history_file: String,
+    /// File that bearer tokens are cached in between runs.
+    pub token_cache: String,
+    pub endpoints: Endpoints,
+    pub clock_check: Option<ClockCheck>,
+    /// Directory of the config file, which relative paths inside it are resolved against.
+    base: PathBuf,
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy)]
 pub enum SnipeTask {
     Mojang,
     Microsoft,
     Giftcode,
 }
 
-#[derive(Deserialize)]
-pub struct Skin {
-    pub file: bool,
-    pub path: String,
-    pub slim: bool,
+impl SnipeTask {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Mojang => "mj",
+            Self::Microsoft => "ms",
+            Self::Giftcode => "prename",
+        }
+    }
+}
+
+/// How the send times of snipe requests are spread out after the snipe time. A bare number
+/// in the config file is a linear spread with that step.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "strategy", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Spread {
+    /// Every request `step` ms after the previous one, across all accounts.
+    Linear { step: u32 },
+    /// Like `Linear`, but with as many requests before the snipe time as after it.
+    Centred { step: u32 },
+    /// The send time of every request in ms relative to the snipe time, in order.
+    Explicit { offsets: Vec<i64> },
+    /// Requests of the same account `step` ms apart and accounts `gap` ms apart.
+    Groups {
+        #[serde(default)]
+        step: u32,
+        gap: u32,
+    },
+    /// Every request at a random time up to `window` ms after the snipe time.
+    Jitter { window: u32 },
+}
+
+impl Default for Spread {
+    fn default() -> Self {
+        Self::Linear { step: 0 }
+    }
+}
+
+impl fmt::Display for Spread {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Linear { step } => write!(f, "linear {step} ms"),
+            Self::Centred { step } => write!(f, "centred {step} ms"),
+            Self::Explicit { offsets } => write!(f, "explicit {offsets:?} ms"),
+            Self::Groups { step, gap } => write!(f, "groups {step} ms, {gap} ms apart"),
+            Self::Jitter { window } => write!(f, "jitter {window} ms"),
+        }
+    }
 }
 
 #[derive(Deserialize)]
 #[serde(untagged)]
-enum AccountVariants {
-    One(One),
-    Two(Two),
-    Three(Three),
+enum SpreadVariants {
+    Step(u32),
+    Strategy(Spread),
+}
+
+impl From<SpreadVariants> for Spread {
+    fn from(item: SpreadVariants) -> Self {
+        match item {
+            SpreadVariants::Step(step) => Self::Linear { step },
+            SpreadVariants::Strategy(spread) => spread,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Endpoints {
+    pub minecraft_services: String,
+    pub mojang_auth: String,
+    pub mojang_api: String,
+    pub droptime: String,
+    pub live_login: String,
+    pub xbl: String,
+    pub xsts: String,
+    pub accept_invalid_certs: bool,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            minecraft_services: constants::MINECRAFT_SERVICES_URL.to_string(),
+            mojang_auth: constants::MOJANG_AUTH_URL.to_string(),
+            mojang_api: constants::MOJANG_API_URL.to_string(),
+            droptime: constants::DROPTIME_URL.to_string(),
+            live_login: constants::LIVE_LOGIN_URL.to_string(),
+            xbl: constants::XBL_URL.to_string(),
+            xsts: constants::XSTS_URL.to_string(),
+            accept_invalid_certs: false,
+        }
+    }
+}
+
+impl Endpoints {
+    /// Checks that every endpoint is a valid base URL and strips trailing slashes so
+    /// paths can be appended with `format!`.
+    pub fn validate(&mut self) -> Result<()> {
+        for (field, url) in [
+            ("minecraft_services", &mut self.minecraft_services),
+            ("mojang_auth", &mut self.mojang_auth),
+            ("mojang_api", &mut self.mojang_api),
+            ("droptime", &mut self.droptime),
+            ("live_login", &mut self.live_login),
+            ("xbl", &mut self.xbl),
+            ("xsts", &mut self.xsts),
+        ] {
+            let trimmed = url.trim_end_matches('/');
+            let parsed = Url::parse(trimmed)
+                .with_context(|| format!("Invalid URL for endpoint {field}: {url}"))?;
+            if parsed.host_str().is_none() {
+                bail!("Endpoint {field} has no host: {url}");
+            }
+            *url = trimmed.to_string();
+        }
+        Ok(())
+    }
 }
 
 #[derive(Deserialize)]
 #[serde(deny_unknown_fields)]
-struct One {
-    email: String,
-    password: String,
+pub struct ClockCheck {
+    #[serde(default)]
+    pub source: ClockSource,
+    #[serde(default = "default_ntp_server")]
+    pub ntp_server: String,
+    #[serde(default = "default_max_skew")]
+    pub max_skew: u32,
+    #[serde(default)]
+    pub correct: bool,
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ClockSource {
+    #[default]
+    Http,
+    Ntp,
+}
+
+fn default_lead_time() -> u32 {
+    constants::CONNECT_LEAD_TIME
+}
+
+fn default_history_file() -> String {
+    constants::HISTORY_PATH.to_string()
+}
+
+fn default_token_cache() -> String {
+    constants::TOKEN_CACHE_PATH.to_string()
+}
+
+fn default_ntp_server() -> String {
+    constants::NTP_SERVER.to_string()
+}
+
+fn default_max_skew() -> u32 {
+    constants::MAX_CLOCK_SKEW
 }
 
 #[derive(Deserialize)]
 #[serde(deny_unknown_fields)]
-struct Two {
-    email: String,
-    password: String,
-    sq_ans: [String; 3],
+pub struct Skin {
+    pub file: bool,
+    pub path: String,
+    pub slim: bool,
+}
+
+impl Skin {
+    /// Checks that the skin file is a PNG of a size Minecraft accepts, or that the skin URL
+    /// is valid.
+    pub fn validate(&self) -> Result<()> {
+        let path = &self.path;
+        if !self.file {
+            let url = Url::parse(path).with_context(|| format!("Invalid skin URL: {path}"))?;
+            if !matches!(url.scheme(), "http" | "https") {
+                bail!("Skin URL is not an HTTP or HTTPS URL: {path}");
+            }
+            return Ok(());
+        }
+        let skin = fs::read(path).with_context(|| format!("Failed to read {path}"))?;
+        if skin.len() < 24 || !skin.starts_with(b"\x89PNG\r\n\x1a\n") || &skin[12..16] != b"IHDR" {
+            bail!("{path} is not a PNG file");
+        }
+        let width = u32::from_be_bytes(skin[16..20].try_into().unwrap());
+        let height = u32::from_be_bytes(skin[20..24].try_into().unwrap());
+        if width != 64 || (height != 64 && height != 32) {
+            bail!("{path} is {width}x{height} pixels, but skins must be 64x64 or 64x32 pixels");
+        }
+        Ok(())
+    }
 }
 
 #[derive(Deserialize)]
 #[serde(deny_unknown_fields)]
-struct Three {
-    bearer: String,
+struct AccountEntry {
+    email: Option<String>,
+    password: Option<String>,
+    sq_ans: Option<[String; 3]>,
+    bearer: Option<String>,
+    vault: Option<String>,
+    #[serde(default)]
+    device_code: bool,
+    offset: Option<u32>,
+    requests: Option<usize>,
+    spread: Option<SpreadVariants>,
 }
 
 #[derive(Clone)]
@@ -81,6 +284,107 @@ pub struct Account {
     pub password: Option<String>,
     pub sq_ans: Option<[String; 3]>,
     pub bearer: Option<String>,
+    /// Label of the vault account the credentials are taken from.
+    pub vault: Option<String>,
+    /// Signs in to the Microsoft account `email` by entering a code on another device.
+    pub device_code: bool,
+    pub overrides: Overrides,
+}
+
+/// Settings of a single account that take precedence over the global ones.
+#[derive(Clone, Default)]
+pub struct Overrides {
+    pub offset: Option<u32>,
+    /// Number of snipe requests sent by the account.
+    pub requests: Option<usize>,
+    /// Spreads out the requests of the account on their own rather than as part of the
+    /// global spread.
+    pub spread: Option<Spread>,
+}
+
+impl Account {
+    /// Takes the credentials from the vault if the account refers to it, and otherwise
+    /// replaces secret references in the credential fields with the secrets they refer to.
+    fn resolve_secrets(&mut self, base: &Path, vault: Option<&Vault>) -> Result<()> {
+        if let Some(label) = &self.vault {
+            if self.email.is_some()
+                || self.password.is_some()
+                || self.sq_ans.is_some()
+                || self.bearer.is_some()
+            {
+                bail!("An account from the vault can't also set `email`, `password`, `sq_ans` or `bearer`");
+            }
+            let account = vault
+                .and_then(|vault| vault.get(label))
+                .with_context(|| format!("The vault has no account labelled {label}"))?;
+            self.email.clone_from(&account.email);
+            if self.device_code {
+                // Only the email is needed to sign in with a device code
+                if self.email.is_none() {
+                    bail!("The vault account {label} has no email to sign in with a device code");
+                }
+                return Ok(());
+            }
+            self.password.clone_from(&account.password);
+            self.sq_ans.clone_from(&account.sq_ans);
+            self.bearer.clone_from(&account.bearer);
+            return Ok(());
+        }
+        for (field, value) in [
+            ("email", &mut self.email),
+            ("password", &mut self.password),
+            ("bearer", &mut self.bearer),
+        ] {
+            if let Some(value) = value {
+                *value = resolve_secret(base, value)
+                    .with_context(|| format!("Failed to resolve `{field}`"))?;
+            }
+        }
+        if let Some(answers) = &mut self.sq_ans {
+            for (idx, answer) in answers.iter_mut().enumerate() {
+                *answer = resolve_secret(base, answer)
+                    .with_context(|| format!("Failed to resolve `sq_ans` #{}", idx + 1))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn validate(&self, mode: SnipeTask) -> Result<()> {
+        match (&self.email, &self.password, &self.bearer, self.device_code) {
+            (Some(_), Some(_), None, false) => (),
+            (None, None, Some(_), false) if self.sq_ans.is_none() => (),
+            (Some(_), None, None, true) if self.sq_ans.is_none() => {
+                if mode == SnipeTask::Mojang {
+                    bail!("Device code sign in only works with Microsoft accounts");
+                }
+            }
+            _ => bail!("Either an email and a password, an email with `device_code` or a bearer token is required"),
+        }
+        if self.overrides.requests == Some(0) {
+            bail!("At least one request is required");
+        }
+        if let Some(spread) = &self.overrides.spread {
+            check_explicit_spread(spread, self.request_count(mode == SnipeTask::Giftcode))?;
+        }
+        Ok(())
+    }
+
+    /// Number of snipe requests the account sends.
+    #[must_use]
+    pub fn request_count(&self, is_gc: bool) -> usize {
+        self.overrides
+            .requests
+            .unwrap_or_else(|| scheduler::request_count(is_gc))
+    }
+
+    /// Identifies the account in output and history without revealing secrets.
+    #[must_use]
+    pub fn label(&self, idx: usize) -> String {
+        self.email
+            .clone()
+            .or_else(|| self.vault.clone())
+            .unwrap_or_else(|| format!("bearer #{}", idx + 1))
+    }
 }
 
 impl From<PrivateConfig> for Config {
@@ -88,24 +392,17 @@ impl From<PrivateConfig> for Config {
         let account_entry = item
             .account_entry
             .into_iter()
-            .map(|entry| match entry {
-                AccountVariants::One(x) => Account {
-                    email: Some(x.email),
-                    password: Some(x.password),
-                    sq_ans: None,
-                    bearer: None,
-                },
-                AccountVariants::Two(x) => Account {
-                    email: Some(x.email),
-                    password: Some(x.password),
-                    sq_ans: Some(x.sq_ans),
-                    bearer: None,
-                },
-                AccountVariants::Three(x) => Account {
-                    email: None,
-                    password: None,
-                    sq_ans: None,
-                    bearer: Some(x.bearer),
+            .map(|entry| Account {
+                email: entry.email,
+                password: entry.password,
+                sq_ans: entry.sq_ans,
+                bearer: entry.bearer,
+                vault: entry.vault,
+                device_code: entry.device_code,
+                overrides: Overrides {
+                    offset: entry.offset,
+                    requests: entry.requests,
+                    spread: entry.spread.map(Spread::from),
                 },
             })
             .collect();
@@ -116,6 +413,13 @@ impl From<PrivateConfig> for Config {
             skin: item.skin,
             name_queue: item.name_queue,
             spread: item.spread,
+            spin_wait: item.spin_wait,
+            lead_time: item.lead_time,
+            history_file: item.history_file,
+            token_cache: item.token_cache,
+            endpoints: item.endpoints,
+            clock_check: item.clock_check,
+            base: PathBuf::new(),
         }
     }
 }
@@ -133,9 +437,43 @@ where
     }
 }
 
-pub fn new() -> Result<Config> {
-    let cfg = read_to_string(constants::CONFIG_PATH)?;
-    let cfg: Config = toml::from_str(&cfg)?;
+/// Checks that an explicit spread has an offset for each of the `required` requests.
+fn check_explicit_spread(spread: &Spread, required: usize) -> Result<()> {
+    if let Spread::Explicit { offsets } = spread {
+        if offsets.len() < required {
+            bail!(
+                "An explicit spread needs {required} offsets, one per request, but has {}",
+                offsets.len()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Accepts either a bare linear step or a `[spread]` table naming a strategy.
+pub(crate) fn to_spread<'de, D>(deserializer: D) -> Result<Spread, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(SpreadVariants::deserialize(deserializer)?.into())
+}
+
+/// Reads and validates the config file at `path`. Relative paths inside it are resolved
+/// against the directory of the config file. The credentials of the accounts are left as
+/// they are written in the config file until they are resolved with
+/// [`Config::resolve_accounts`].
+pub fn new(path: &Path) -> Result<Config> {
+    let cfg = read_to_string(path)?;
+    let mut cfg: Config = toml::from_str(&cfg)?;
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    cfg.base = base.to_path_buf();
+    cfg.history_file = resolve(base, &cfg.history_file);
+    cfg.token_cache = resolve(base, &cfg.token_cache);
+    if let Some(skin) = &mut cfg.skin {
+        if skin.file {
+            skin.path = resolve(base, &skin.path);
+        }
+    }
     if cfg.account_entry.is_empty() {
         bail!("No accounts provided in config file");
     }
@@ -144,5 +482,220 @@ pub fn new() -> Result<Config> {
             bail!("No name provided in name queue");
         }
     }
+    let is_gc = cfg.mode == SnipeTask::Giftcode;
+    let accounts = if is_gc {
+        cfg.account_entry.len().min(10)
+    } else {
+        1
+    };
+    let required: usize = cfg.account_entry[..accounts]
+        .iter()
+        .filter(|account| account.overrides.spread.is_none())
+        .map(|account| account.request_count(is_gc))
+        .sum();
+    check_explicit_spread(&cfg.spread, required)?;
+    cfg.endpoints.validate()?;
     Ok(cfg)
 }
+
+impl Config {
+    /// Replaces the credentials of every account with the secrets they refer to and
+    /// validates the accounts. Secret references may run commands, so this is only done
+    /// before signing in. `unlock` is only called if an account refers to the vault.
+    pub fn resolve_accounts(&mut self, unlock: impl FnOnce() -> Result<Vault>) -> Result<()> {
+        let vault = if self
+            .account_entry
+            .iter()
+            .any(|account| account.vault.is_some())
+        {
+            Some(unlock().with_context(|| "Failed to unlock the vault")?)
+        } else {
+            None
+        };
+        for (idx, account) in self.account_entry.iter_mut().enumerate() {
+            account
+                .resolve_secrets(&self.base, vault.as_ref())
+                .and_then(|()| account.validate(self.mode))
+                .with_context(|| format!("Invalid account entry #{}", idx + 1))?;
+        }
+        Ok(())
+    }
+}
+
+fn resolve(base: &Path, path: &str) -> String {
+    base.join(path).to_string_lossy().to_string()
+}
+
+/// Resolves a secret reference, which is either `env:VAR`, `file:/path` or `cmd:<command>`
+/// whose stdout is the secret. `raw:` escapes a secret that starts with one of these
+/// prefixes. Any other value is the secret itself.
+fn resolve_secret(base: &Path, value: &str) -> Result<String> {
+    let secret = if let Some(secret) = value.strip_prefix("raw:") {
+        return Ok(secret.to_string());
+    } else if let Some(var) = value.strip_prefix("env:") {
+        env::var(var).with_context(|| format!("Environment variable {var} is not set"))?
+    } else if let Some(path) = value.strip_prefix("file:") {
+        let path = base.join(path);
+        read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?
+    } else if let Some(command) = value.strip_prefix("cmd:") {
+        let mut shell = if cfg!(windows) {
+            let mut shell = Command::new("cmd");
+            shell.arg("/C");
+            shell
+        } else {
+            let mut shell = Command::new("sh");
+            shell.arg("-c");
+            shell
+        };
+        if !base.as_os_str().is_empty() {
+            shell.current_dir(base);
+        }
+        let output = shell
+            .arg(command)
+            .stdin(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .output()
+            .with_context(|| format!("Failed to run {command}"))?;
+        if !output.status.success() {
+            bail!("{command} exited with {}", output.status);
+        }
+        String::from_utf8(output.stdout)
+            .with_context(|| format!("Output of {command} is not valid UTF-8"))?
+    } else {
+        return Ok(value.to_string());
+    };
+    let secret = secret.trim_end_matches(['\r', '\n']);
+    if secret.is_empty() {
+        bail!("{value} resolved to an empty value");
+    }
+    Ok(secret.to_string())
+}
+
+/// Rewrites the top-level `offset` of the config file at `path`, leaving the rest of the
+/// file as is.
+pub fn write_offset(path: &Path, offset: u32) -> Result<()> {
+    static OFFSET_RE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?m)^[ \t]*offset[ \t]*=.*$").unwrap());
+    static TABLE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?m)^[ \t]*\[").unwrap());
+    let cfg = read_to_string(path)?;
+    let top_level_end = TABLE_RE.find(&cfg).map_or(cfg.len(), |table| table.start());
+    let line = format!("offset = {offset}");
+    let cfg = match OFFSET_RE.find(&cfg[..top_level_end]) {
+        Some(existing) => format!(
+            "{}{line}{}",
+            &cfg[..existing.start()],
+            &cfg[existing.end()..]
+        ),
+        None => format!("{line}\n{cfg}"),
+    };
+    write(path, cfg)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vault::VaultAccount;
+    use std::{fs, process};
+
+    /// Rewrites the offset of a config file containing `cfg` and returns the new contents.
+    fn rewrite(name: &str, cfg: &str, offset: u32) -> String {
+        let path = std::env::temp_dir().join(format!("buckshot-{}-{name}.toml", process::id()));
+        fs::write(&path, cfg).unwrap();
+        write_offset(&path, offset).unwrap();
+        let cfg = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        cfg
+    }
+
+    #[test]
+    fn resolve_secret_escapes() {
+        let base = Path::new("");
+        assert_eq!(resolve_secret(base, "hunter2").unwrap(), "hunter2");
+        assert_eq!(resolve_secret(base, "raw:cmd:rm").unwrap(), "cmd:rm");
+        assert_eq!(resolve_secret(base, "raw:raw:x").unwrap(), "raw:x");
+        assert_eq!(resolve_secret(base, "raw:").unwrap(), "");
+        assert!(resolve_secret(base, "env:BUCKSHOT_TEST_UNSET_VARIABLE").is_err());
+    }
+
+    #[test]
+    fn vault_account_with_device_code() {
+        let mut vault = Vault::create(Path::new("buckshot.vault"), "passphrase");
+        for (label, password) in [("email-only", None), ("with-password", Some("pw"))] {
+            vault
+                .add(VaultAccount {
+                    label: label.to_string(),
+                    email: Some(format!("{label}@example.com")),
+                    password: password.map(str::to_string),
+                    sq_ans: None,
+                    bearer: None,
+                })
+                .unwrap();
+        }
+        for label in ["email-only", "with-password"] {
+            let mut account = Account {
+                email: None,
+                password: None,
+                sq_ans: None,
+                bearer: None,
+                vault: Some(label.to_string()),
+                device_code: true,
+                overrides: Overrides::default(),
+            };
+            account
+                .resolve_secrets(Path::new(""), Some(&vault))
+                .unwrap();
+            assert_eq!(
+                account.email.as_deref(),
+                Some(&*format!("{label}@example.com"))
+            );
+            assert!(account.password.is_none());
+            account.validate(SnipeTask::Microsoft).unwrap();
+            assert!(account.validate(SnipeTask::Mojang).is_err());
+        }
+    }
+
+    #[test]
+    fn write_offset_replaces_top_level_offset() {
+        let cfg = "\
+# Snipe offset
+offset = 9
+mode = \"ms\"
+
+[[account_entry]]
+email = \"a@example.com\"
+password = \"pw\"
+offset = 40
+
+[name_queue]
+queue = [\"foo\"]
+never_stop_sniping = false
+";
+        let expected = cfg.replace("offset = 9", "offset = 21");
+        assert_eq!(rewrite("replace", cfg, 21), expected);
+    }
+
+    #[test]
+    fn write_offset_ignores_offsets_in_tables() {
+        let cfg = "\
+mode = \"ms\"
+
+[[account_entry]]
+email = \"a@example.com\"
+offset = 40
+";
+        let rewritten = rewrite("insert", cfg, 21);
+        assert_eq!(rewritten, format!("offset = 21\n{cfg}"));
+        let value: toml::Value = toml::from_str(&rewritten).unwrap();
+        assert_eq!(value["offset"].as_integer(), Some(21));
+        assert_eq!(value["account_entry"][0]["offset"].as_integer(), Some(40));
+    }
+
+    #[test]
+    fn write_offset_without_tables() {
+        assert_eq!(
+            rewrite("plain", "mode = \"ms\"\n  offset   = 3\n", 7),
+            "mode = \"ms\"\noffset = 7\n"
+        );
+    }
+}
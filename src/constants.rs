@@ -1,2 +1,24 @@
 pub const CONFIG_PATH: &str = "config.toml";
+pub const HISTORY_PATH: &str = "snipe_history.jsonl";
+pub const TOKEN_CACHE_PATH: &str = "token_cache.json";
+pub const TOKEN_EXPIRY_MARGIN: i64 = 60;
+pub const TOKEN_REFRESH_LEAD: i64 = 120;
+pub const TOKEN_REFRESH_LEAD_PER_ACCOUNT: i64 = 30;
+pub const VAULT_PATH: &str = "buckshot.vault";
+pub const VAULT_MAGIC: &[u8] = b"BSVAULT1";
+pub const VAULT_KDF_ITERATIONS: u32 = 600_000;
 pub const BARRIER_THRESHOLD: u32 = 27;
+pub const CONNECT_LEAD_TIME: u32 = 32;
+pub const MAX_RECONNECTS: usize = 10;
+pub const FINAL_BYTES: usize = 2;
+pub const MAX_RESPONSE_SIZE: usize = 64 * 1024;
+pub const RESPONSE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+pub const MINECRAFT_SERVICES_URL: &str = "https://api.minecraftservices.com";
+pub const MOJANG_AUTH_URL: &str = "https://authserver.mojang.com";
+pub const MOJANG_API_URL: &str = "https://api.mojang.com";
+pub const DROPTIME_URL: &str = "http://api.star.shopping";
+pub const LIVE_LOGIN_URL: &str = "https://login.live.com";
+pub const XBL_URL: &str = "https://user.auth.xboxlive.com";
+pub const XSTS_URL: &str = "https://xsts.auth.xboxlive.com";
+pub const NTP_SERVER: &str = "pool.ntp.org:123";
+pub const MAX_CLOCK_SKEW: u32 = 1000;
@@ -0,0 +1,193 @@
+use crate::{
+    clock::to_millis,
+    config::{self, Overrides, Spread},
+    constants,
+    http::HttpResponse,
+    sniper::SnipeJob,
+    sockets::{RequestOutcome, ResData},
+};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{read_to_string, OpenOptions},
+    io::Write,
+    path::Path,
+};
+
+/// One line of the snipe history file.
+#[derive(Serialize, Deserialize)]
+pub struct SnipeRecord {
+    pub recorded_at: DateTime<Utc>,
+    pub name: String,
+    pub mode: String,
+    pub droptime: DateTime<Utc>,
+    pub droptime_source: DroptimeSource,
+    /// Minecraft services API the snipe was sent to. Records written before it was recorded
+    /// are assumed to have been sent to the real API.
+    #[serde(default = "default_endpoint")]
+    pub endpoint: String,
+    pub offset: u32,
+    #[serde(deserialize_with = "config::to_spread")]
+    pub spread: Spread,
+    pub spin_wait: u32,
+    #[serde(default = "default_lead_time")]
+    pub lead_time: u32,
+    /// Server clock minus local clock in milliseconds.
+    pub skew: f64,
+    pub success: bool,
+    /// Label of the account that claimed the name.
+    pub winner: Option<String>,
+    pub requests: Vec<RequestRecord>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DroptimeSource {
+    /// Looked up from the droptime API.
+    Api,
+    /// Passed on the command line.
+    Cli,
+}
+
+/// A single snipe request. Times are in milliseconds relative to the droptime on the local
+/// clock.
+#[derive(Serialize, Deserialize)]
+pub struct RequestRecord {
+    pub account: String,
+    /// Offset and spread the account sent the request with. Records written before these
+    /// were recorded only have the offset and spread of the snipe.
+    #[serde(default)]
+    pub offset: Option<u32>,
+    #[serde(default)]
+    pub spread: Option<Spread>,
+    pub outcome: String,
+    pub status: Option<u16>,
+    pub reason: Option<String>,
+    pub send_time: f64,
+    pub connect_start: Option<f64>,
+    /// When the TCP connection was established.
+    pub connected: Option<f64>,
+    pub handshake: Option<f64>,
+    pub head_written: Option<f64>,
+    pub sent: Option<f64>,
+    pub first_byte: Option<f64>,
+    /// When each lost connection was noticed.
+    #[serde(default)]
+    pub reconnects: Vec<f64>,
+}
+
+impl RequestRecord {
+    /// The offset the request was sent with, falling back to that of the snipe `record`.
+    #[must_use]
+    pub fn offset(&self, record: &SnipeRecord) -> u32 {
+        self.offset.unwrap_or(record.offset)
+    }
+
+    /// The spread the request was sent with, falling back to that of the snipe `record`.
+    #[must_use]
+    pub fn spread<'a>(&'a self, record: &'a SnipeRecord) -> &'a Spread {
+        self.spread.as_ref().unwrap_or(&record.spread)
+    }
+}
+
+impl SnipeRecord {
+    /// `labels` names the account behind each bearer token the snipe was executed with, and
+    /// `overrides` holds the overrides of those accounts. `endpoint` is the Minecraft services
+    /// API the snipe was sent to.
+    #[must_use]
+    pub fn new(
+        job: &SnipeJob,
+        endpoint: &str,
+        droptime_source: DroptimeSource,
+        res_data: &[ResData],
+        labels: &[String],
+        overrides: &[Overrides],
+    ) -> Self {
+        let droptime = job.local_droptime();
+        let relative = |time: Option<DateTime<Utc>>| time.map(|time| to_millis(time - droptime));
+        let requests = res_data
+            .iter()
+            .map(|res| RequestRecord {
+                account: labels[res.account_idx].clone(),
+                offset: Some(overrides[res.account_idx].offset.unwrap_or(job.offset)),
+                spread: Some(
+                    overrides[res.account_idx]
+                        .spread
+                        .clone()
+                        .unwrap_or_else(|| job.spread.clone()),
+                ),
+                outcome: res.outcome.to_string(),
+                status: match res.outcome {
+                    RequestOutcome::Status(status) => Some(status),
+                    _ => None,
+                },
+                reason: res.response.as_ref().and_then(HttpResponse::reason),
+                send_time: to_millis(res.send_time - droptime),
+                connect_start: relative(res.timeline.connect_start),
+                connected: relative(res.timeline.connected),
+                handshake: res.timeline.handshake().map(to_millis),
+                head_written: relative(res.timeline.head_written),
+                sent: relative(res.timeline.sent),
+                first_byte: relative(res.timeline.first_byte),
+                reconnects: res
+                    .timeline
+                    .reconnects
+                    .iter()
+                    .map(|reconnect| to_millis(reconnect.at - droptime))
+                    .collect(),
+            })
+            .collect();
+        let winner = res_data
+            .iter()
+            .find(|res| res.is_success())
+            .map(|res| labels[res.account_idx].clone());
+        Self {
+            recorded_at: Utc::now(),
+            name: job.name.clone(),
+            mode: job.task.as_str().to_string(),
+            droptime: job.droptime,
+            droptime_source,
+            endpoint: endpoint.to_string(),
+            offset: job.offset,
+            spread: job.spread.clone(),
+            spin_wait: job.spin_wait,
+            lead_time: job.lead_time,
+            skew: to_millis(job.skew),
+            success: winner.is_some(),
+            winner,
+            requests,
+        }
+    }
+}
+
+fn default_endpoint() -> String {
+    constants::MINECRAFT_SERVICES_URL.to_string()
+}
+
+fn default_lead_time() -> u32 {
+    constants::CONNECT_LEAD_TIME
+}
+
+/// Appends `record` to the history file as a single line of JSON.
+pub fn append(path: &Path, record: &SnipeRecord) -> Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Reads every record in the history file, oldest first.
+pub fn read(path: &Path) -> Result<Vec<SnipeRecord>> {
+    let contents = read_to_string(path)?;
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(idx, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("Malformed record on line {}", idx + 1))
+        })
+        .collect()
+}
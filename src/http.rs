@@ -0,0 +1,254 @@
+use crate::{clock::Anchor, constants::MAX_RESPONSE_SIZE};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// A complete HTTP response read from a raw socket.
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    /// When the first byte of the response arrived.
+    pub received: DateTime<Utc>,
+}
+
+impl HttpResponse {
+    #[must_use]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Extracts a human-readable explanation of the status from the response body.
+    #[must_use]
+    pub fn reason(&self) -> Option<String> {
+        let body = self.body.trim();
+        if body.is_empty() {
+            return None;
+        }
+        if let Ok(json) = serde_json::from_str::<serde_json::Value>(body) {
+            let reason = json["errorMessage"]
+                .as_str()
+                .or_else(|| json["details"]["status"].as_str())
+                .or_else(|| json["error"].as_str());
+            if let Some(reason) = reason {
+                return Some(reason.to_string());
+            }
+        }
+        Some(body.chars().take(100).collect())
+    }
+}
+
+/// Reads a response up to `MAX_RESPONSE_SIZE` bytes. The connection is expected to be
+/// closed by the server after the response unless a `Content-Length` is given or the body
+/// is chunked. The arrival time is taken from `anchor`.
+pub async fn read_response<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    anchor: &Anchor,
+) -> Result<HttpResponse> {
+    let mut buf = Vec::new();
+    let mut received = None;
+    let head_end = loop {
+        let read = read_chunk(reader, &mut buf).await?;
+        received.get_or_insert_with(|| anchor.now());
+        if let Some(pos) = find(&buf, b"\r\n\r\n") {
+            break pos;
+        }
+        if read == 0 {
+            bail!("Connection closed before the response head was complete");
+        }
+    };
+    let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().unwrap_or_default();
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .with_context(|| format!("Malformed HTTP status line: {status_line}"))?;
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    };
+    let chunked = header("Transfer-Encoding").is_some_and(|value| value.contains("chunked"));
+    let content_length: Option<usize> = if chunked {
+        None
+    } else {
+        header("Content-Length").and_then(|length| length.parse().ok())
+    };
+    let body_start = head_end + 4;
+    loop {
+        if content_length.is_some_and(|length| buf.len() - body_start >= length) {
+            break;
+        }
+        if chunked && decode_chunked(&buf[body_start..])?.is_some() {
+            break;
+        }
+        if read_chunk(reader, &mut buf).await? == 0 {
+            break;
+        }
+    }
+    let mut body = buf.split_off(body_start);
+    if chunked {
+        body = decode_chunked(&body)?.with_context(|| "Truncated chunked body")?;
+    } else if let Some(length) = content_length {
+        body.truncate(length);
+    }
+    Ok(HttpResponse {
+        status,
+        headers,
+        body: String::from_utf8_lossy(&body).to_string(),
+        received: received.unwrap_or_else(|| anchor.now()),
+    })
+}
+
+async fn read_chunk<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut Vec<u8>) -> Result<usize> {
+    let mut chunk = [0; 4096];
+    let read = reader.read(&mut chunk).await?;
+    if buf.len() + read > MAX_RESPONSE_SIZE {
+        bail!("Response exceeded {MAX_RESPONSE_SIZE} bytes");
+    }
+    buf.extend_from_slice(&chunk[..read]);
+    Ok(read)
+}
+
+/// Decodes a chunked body, which is `None` until the last chunk has arrived.
+fn decode_chunked(mut data: &[u8]) -> Result<Option<Vec<u8>>> {
+    let mut body = Vec::new();
+    loop {
+        let Some(line_end) = find(data, b"\r\n") else {
+            return Ok(None);
+        };
+        let size_line = String::from_utf8_lossy(&data[..line_end]);
+        let size_hex = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_hex, 16)
+            .with_context(|| format!("Malformed chunk size: {size_hex}"))?;
+        data = &data[line_end + 2..];
+        if size == 0 {
+            return Ok(Some(body));
+        }
+        if data.len() < size + 2 {
+            return Ok(None);
+        }
+        body.extend_from_slice(&data[..size]);
+        data = &data[size + 2..];
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::AsyncWriteExt,
+        time::{timeout, Duration},
+    };
+
+    async fn parse(response: &[u8]) -> Result<HttpResponse> {
+        let mut reader = response;
+        read_response(&mut reader, &Anchor::capture()).await
+    }
+
+    #[tokio::test]
+    async fn content_length() {
+        let response = parse(
+            b"HTTP/1.1 403 Forbidden\r\nContent-Type: application/json\r\nContent-Length: 2\r\n\r\n{}trailing",
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status, 403);
+        assert_eq!(response.header("content-type"), Some("application/json"));
+        assert_eq!(response.body, "{}");
+    }
+
+    #[tokio::test]
+    async fn body_until_eof() {
+        let response = parse(b"HTTP/1.1 200 OK\r\n\r\nhello").await.unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, "hello");
+    }
+
+    #[tokio::test]
+    async fn chunked() {
+        let response = parse(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nContent-Length: 1\r\n\r\n5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\n\r\n",
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.body, "hello world");
+    }
+
+    #[tokio::test]
+    async fn chunked_without_eof() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        server
+            .write_all(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nok\r\n0\r\n\r\n")
+            .await
+            .unwrap();
+        // The server keeps the connection open
+        let response = timeout(
+            Duration::from_secs(1),
+            read_response(&mut client, &Anchor::capture()),
+        )
+        .await
+        .expect("a complete chunked response should not wait for EOF")
+        .unwrap();
+        assert_eq!(response.body, "ok");
+        drop(server);
+    }
+
+    #[tokio::test]
+    async fn truncated_chunked() {
+        let response =
+            parse(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhel").await;
+        assert!(response.is_err());
+        let response = parse(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n").await;
+        assert!(response.is_err());
+    }
+
+    #[tokio::test]
+    async fn truncated_head() {
+        assert!(parse(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n")
+            .await
+            .is_err());
+        assert!(parse(b"").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn malformed_status_line() {
+        assert!(parse(b"garbage\r\n\r\n").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn size_limit() {
+        let mut response = b"HTTP/1.1 200 OK\r\n\r\n".to_vec();
+        response.resize(MAX_RESPONSE_SIZE + 1, b'a');
+        assert!(parse(&response).await.is_err());
+        response.truncate(MAX_RESPONSE_SIZE);
+        assert!(parse(&response).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn reason() {
+        let response =
+            parse(b"HTTP/1.1 403 Forbidden\r\n\r\n{\"errorMessage\":\"Name is already taken\"}")
+                .await
+                .unwrap();
+        assert_eq!(response.reason().as_deref(), Some("Name is already taken"));
+        let response = parse(b"HTTP/1.1 204 No Content\r\n\r\n").await.unwrap();
+        assert_eq!(response.reason(), None);
+    }
+}
@@ -0,0 +1,21 @@
+#![warn(clippy::pedantic)]
+#![allow(clippy::missing_errors_doc, clippy::missing_panics_doc)]
+
+pub mod analysis;
+pub mod auth;
+pub mod calibrate;
+pub mod clock;
+pub mod config;
+pub mod constants;
+pub mod history;
+pub mod http;
+pub mod msauth;
+pub mod requests;
+pub mod scheduler;
+pub mod sockets;
+pub mod tokens;
+pub mod vault;
+
+mod sniper;
+
+pub use sniper::{SnipeJob, Sniper};
@@ -1,40 +1,80 @@
 #![warn(clippy::pedantic)]
 
 mod cli;
-mod config;
-mod constants;
-mod msauth;
-mod requests;
-mod sockets;
 
 use ansi_term::Colour::{Cyan, Green, Red};
 use anyhow::{bail, Context, Result};
-use chrono::{Duration, Local, TimeZone};
+use buckshot::{
+    analysis::{self, Breakdown},
+    auth::{AuthEvent, Authenticator},
+    calibrate::{self, Stats},
+    clock,
+    config::{self, Account, ClockCheck, Config, Overrides, SnipeTask},
+    history::{self, DroptimeSource, SnipeRecord},
+    http::HttpResponse,
+    requests::DroptimeData,
+    scheduler::Schedule,
+    sockets::{Reconnect, ReconnectHandler, ResData, Timeline},
+    tokens,
+    vault::Vault,
+    SnipeJob, Sniper,
+};
+use chrono::{DateTime, Duration, Local, TimeZone, Utc};
+use serde_json::json;
 use std::{
+    fmt::Display,
     io::{stdout, Write},
-    thread::sleep,
+    path::Path,
+    sync::Arc,
 };
+use tokio::time::sleep;
 
 #[tokio::main]
 #[allow(clippy::too_many_lines)]
 async fn main() -> Result<()> {
-    type SnipeTask = config::SnipeTask;
     let args = cli::Args::new();
-    let mut config = config::new().with_context(|| {
+    if let Some(cli::Command::Vault(command)) = &args.command {
+        return vault(&args.vault_path(), command);
+    }
+    let mut config = config::new(&args.config).with_context(|| {
         format!(
             "Failed to get config options from {}",
-            constants::CONFIG_PATH
+            args.config.display()
         )
     })?;
-    let task = &config.mode;
-    if config.name_queue.is_none() || !config.name_queue.clone().unwrap().never_stop_sniping {
-        if task != &SnipeTask::Giftcode && config.account_entry.len() > 1 {
-            bail!("Unable to use more than one normal account");
-        } else if config.account_entry.len() > 10 {
-            bail!("Unable to use more than 10 prename accounts");
+    args.override_endpoints(&mut config.endpoints);
+    config.endpoints.validate()?;
+    match args.command {
+        Some(cli::Command::Calibrate { samples, write }) => {
+            return calibrate(&config, &args.config, samples, write).await;
         }
+        Some(cli::Command::Stats {
+            last,
+            all_endpoints,
+        }) => return stats(&config, last, all_endpoints),
+        Some(cli::Command::Check | cli::Command::Vault(_)) | None => (),
     }
-    let name_list = if let Some(name) = args.name {
+    // Only resolve secrets when signing in, as resolving them may prompt or run commands
+    let is_check = matches!(args.command, Some(cli::Command::Check));
+    if is_check || !args.dry_run {
+        config
+            .resolve_accounts(|| {
+                let path = args.vault_path();
+                Vault::open(&path, &cli::get_vault_passphrase(false)?)
+            })
+            .with_context(|| {
+                format!(
+                    "Failed to get config options from {}",
+                    args.config.display()
+                )
+            })?;
+    }
+    if is_check {
+        return check(&args, &config).await;
+    }
+    let task = config.mode;
+    check_account_count(&config)?;
+    let name_list = if let Some(name) = args.name.clone() {
         vec![name]
     } else if let Some(x) = &config.name_queue {
         x.queue.clone()
@@ -42,191 +82,136 @@ async fn main() -> Result<()> {
         let name = cli::get_name_choice().with_context(|| "Failed to get name choice")?;
         vec![name]
     };
-    let requestor = requests::Requests::new()?;
+    let sniper = Sniper::new(&config.endpoints)?;
+    if args.dry_run {
+        return dry_run(&args, &config, &sniper, &name_list).await;
+    }
+    let mut authenticator = Authenticator::new(&sniper, &config, &print_auth_event)?;
     for (count, name) in name_list.into_iter().enumerate() {
         let name = name.trim();
         if count != 0 {
             writeln!(stdout(), "Moving on to next name...")?;
             writeln!(stdout(), "Waiting 20 seconds to prevent rate limiting...")?;
-            sleep(std::time::Duration::from_secs(20));
+            sleep(std::time::Duration::from_secs(20)).await;
         }
+        let droptime_source = if args.timestamp.is_some() {
+            DroptimeSource::Cli
+        } else {
+            DroptimeSource::Api
+        };
         let droptime = if let Some(timestamp) = args.timestamp {
-            Local.timestamp(timestamp, 0)
+            Utc.timestamp(timestamp, 0)
         } else {
             writeln!(stdout(), "Initializing...")?;
-            match requestor
-                .check_name_availability_time(name)
-                .with_context(|| format!("Failed to get the droptime of {}", name))?
-            {
-                requests::DroptimeData::Available(droptime) => droptime,
-                requests::DroptimeData::Unavailable(error) => {
-                    writeln!(
-                        stdout(),
-                        "{}",
-                        Red.paint(format!("Failed to get the droptime of {}: {}", name, error))
-                    )?;
+            match sniper.droptime(name).await? {
+                DroptimeData::Available(droptime) => droptime,
+                DroptimeData::Unavailable(error) => {
+                    print_droptime_error(name, &error)?;
                     continue;
                 }
             }
         };
-        let formatted_droptime = droptime.format("%F %T");
+        let mut job = SnipeJob {
+            name: name.to_string(),
+            droptime,
+            offset: config.offset,
+            spread: config.spread.clone(),
+            spin_wait: config.spin_wait,
+            lead_time: config.lead_time,
+            task,
+            skew: Duration::zero(),
+        };
         writeln!(
             stdout(),
             "Sniping {} at {} with an offset of {} ms",
             name,
-            formatted_droptime,
-            config.offset
+            droptime.with_timezone(&Local).format("%F %T"),
+            job.offset
         )?;
-        let snipe_time = droptime - Duration::milliseconds(i64::from(config.offset));
-        let setup_time = snipe_time - Duration::hours(12);
-        if Local::now() < setup_time {
-            let sleep_duration = (setup_time - Local::now())
+        authenticator.warn_expiring_bearers(&config.account_entry, job.token_deadline())?;
+        let setup_time = job.setup_time();
+        if Utc::now() < setup_time {
+            let sleep_duration = (setup_time - Utc::now())
                 .to_std()
                 .unwrap_or(std::time::Duration::ZERO);
-            sleep(sleep_duration);
+            sleep(sleep_duration).await;
             if args.timestamp.is_none() {
-                if let requests::DroptimeData::Unavailable(error) = requestor
-                    .check_name_availability_time(name)
-                    .with_context(|| format!("Failed to get the droptime of {}", name))?
-                {
-                    writeln!(
-                        stdout(),
-                        "{}",
-                        Red.paint(format!("Failed to get the droptime of {}: {}", name, error))
-                    )?;
+                if let DroptimeData::Unavailable(error) = sniper.droptime(name).await? {
+                    print_droptime_error(name, &error)?;
                     continue;
                 }
             }
         }
-        let mut bearer_tokens = Vec::new();
-        let mut account_idx = 0;
-        for (count, account) in config.account_entry.clone().iter().enumerate() {
-            let bearer_token = if let Some(bearer) = account.bearer.clone() {
-                bearer
-            } else {
-                if count != 0 {
-                    writeln!(stdout(), "Waiting 20 seconds to prevent rate limiting...")?;
-                    sleep(std::time::Duration::from_secs(20));
-                }
-                let (email, password) = (
-                    account.email.as_ref().unwrap(),
-                    account.password.as_ref().unwrap(),
-                );
-                let bearer = if task == &SnipeTask::Mojang {
-                    match requestor
-                        .authenticate_mojang(email, password, &account.sq_ans)
-                        .with_context(|| {
-                            format!("Failed to authenticate the Mojang account {}", email)
-                        }) {
-                        Ok(x) => x,
-                        Err(y) => {
-                            if config.account_entry.len() == 1 {
-                                bail!(y);
-                            }
-                            writeln!(stdout(), "{}", Red.paint("Failed to authenticate a Mojang account, moving on to next account..."))?;
-                            config.account_entry.remove(account_idx);
-                            continue;
-                        }
-                    }
-                } else {
-                    let authenticator = msauth::Auth::new(email, password)
-                        .with_context(|| "Error creating Microsoft authenticator")?;
-                    match authenticator.authenticate().with_context(|| {
-                        format!("Failed to authenticate the Microsoft account {}", email)
-                    }) {
-                        Ok(x) => x,
-                        Err(y) => {
-                            if config.account_entry.len() == 1 {
-                                bail!(y);
-                            }
-                            writeln!(stdout(), "{}", Red.paint("Failed to authenticate a Microsoft account, moving on to next account..."))?;
-                            config.account_entry.remove(account_idx);
-                            continue;
-                        }
-                    }
-                };
-                if task != &SnipeTask::Giftcode {
-                    if let Err(y) = requestor
-                        .check_name_change_eligibility(&bearer)
-                        .with_context(|| {
-                            format!("Failed to check name change eligibility of {}", email)
-                        })
-                    {
-                        if config.account_entry.len() == 1 {
-                            bail!(y);
-                        }
-                        writeln!(
-                            stdout(),
-                            "{}",
-                            Red.paint(format!(
-                                "Failed to check name change eligibility of {}",
-                                email
-                            ))
-                        )?;
-                        config.account_entry.remove(account_idx);
-                        continue;
-                    }
-                }
-                bearer
-            };
-            bearer_tokens.push(bearer_token);
-            if task != &SnipeTask::Giftcode
-                || (task == &SnipeTask::Giftcode && bearer_tokens.len() == 10)
-            {
-                break;
-            }
-            account_idx += 1;
-        }
-        if bearer_tokens.is_empty() {
-            bail!("No Microsoft accounts left to use");
-        }
+        let mut bearer_tokens = authenticator
+            .sign_in(&mut config, job.token_deadline())
+            .await?;
         writeln!(stdout(), "{}", Green.paint("Successfully signed in"))?;
-        writeln!(stdout(), "Setup complete")?;
-        let mut is_success = None;
-        let is_gc = task == &SnipeTask::Giftcode;
-        let res_data =
-            sockets::snipe_executor(name, &bearer_tokens, snipe_time, is_gc, config.spread)
-                .await
-                .with_context(|| format!("Failed to execute the snipe of {}", name))?;
-        for res in res_data {
-            let formatted_timestamp = res.timestamp.format("%F %T%.6f");
-            match res.status {
-                200 => {
-                    writeln!(
-                        stdout(),
-                        "[{}] {} @ {}",
-                        Green.paint("success"),
-                        Green.paint("200"),
-                        Cyan.paint(format!("{}", formatted_timestamp))
-                    )?;
-                    is_success = Some(res.account_idx);
-                }
-                status => {
-                    writeln!(
-                        stdout(),
-                        "[{}] {} @ {}",
-                        Red.paint("fail"),
-                        Red.paint(format!("{}", status)),
-                        Cyan.paint(format!("{}", formatted_timestamp))
-                    )?;
-                }
+        if let Some(clock_check) = &config.clock_check {
+            job.skew = check_clock(&sniper, clock_check).await?;
+        }
+        let overrides: Vec<Overrides> = config
+            .account_entry
+            .iter()
+            .take(bearer_tokens.len())
+            .map(|account| account.overrides.clone())
+            .collect();
+        // Accounts that failed to sign in were replaced by later ones, which may send more
+        // requests than the spread has offsets for
+        let schedule = match job.schedule(&overrides) {
+            Ok(schedule) => schedule,
+            Err(error) => {
+                writeln!(
+                    stdout(),
+                    "{}",
+                    Red.paint(format!("Failed to schedule the snipe of {name}: {error:#}"))
+                )?;
+                continue;
             }
+        };
+        authenticator
+            .refresh_expiring_tokens(&config.account_entry, &job, &mut bearer_tokens)
+            .await?;
+        writeln!(stdout(), "Setup complete")?;
+        let labels: Vec<String> = config
+            .account_entry
+            .iter()
+            .take(bearer_tokens.len())
+            .enumerate()
+            .map(|(idx, account)| account.label(idx))
+            .collect();
+        let on_reconnect = reconnect_logger(labels.clone(), job.local_droptime());
+        let res_data = sniper
+            .snipe(&job, &bearer_tokens, &schedule, &on_reconnect)
+            .await?;
+        let is_success = print_results(&res_data, job.local_droptime())?;
+        let record = SnipeRecord::new(
+            &job,
+            &config.endpoints.minecraft_services,
+            droptime_source,
+            &res_data,
+            &labels,
+            &overrides,
+        );
+        if let Err(error) = history::append(Path::new(&config.history_file), &record) {
+            writeln!(
+                stdout(),
+                "{}",
+                Red.paint(format!(
+                    "Failed to record snipe in {}: {error}",
+                    config.history_file
+                ))
+            )?;
         }
         if let Some(account_idx) = is_success {
             writeln!(
                 stdout(),
                 "{}",
-                Green.paint(format!("Successfully sniped {}!", name))
+                Green.paint(format!("Successfully sniped {name}!"))
             )?;
             if let Some(skin) = &config.skin {
-                let skin_model = if skin.slim { "slim" } else { "classic" }.to_string();
-                requestor
-                    .upload_skin(
-                        &bearer_tokens[account_idx],
-                        &skin.path,
-                        skin_model,
-                        skin.file,
-                    )
+                sniper
+                    .change_skin(&bearer_tokens[account_idx], skin)
+                    .await
                     .with_context(|| {
                         format!(
                             "Failed to change the skin of {}",
@@ -243,7 +228,686 @@ async fn main() -> Result<()> {
             }
             break;
         }
-        writeln!(stdout(), "Failed to snipe {}", name)?;
+        writeln!(stdout(), "Failed to snipe {name}")?;
+    }
+    Ok(())
+}
+
+async fn dry_run(
+    args: &cli::Args,
+    config: &Config,
+    sniper: &Sniper,
+    name_list: &[String],
+) -> Result<()> {
+    let account_count = if config.mode == SnipeTask::Giftcode {
+        10
+    } else {
+        1
+    };
+    let labels: Vec<String> = config
+        .account_entry
+        .iter()
+        .take(account_count)
+        .enumerate()
+        .map(|(idx, account)| account.label(idx))
+        .collect();
+    let overrides: Vec<Overrides> = config
+        .account_entry
+        .iter()
+        .take(account_count)
+        .map(|account| account.overrides.clone())
+        .collect();
+    for name in name_list {
+        let name = name.trim();
+        let droptime = if let Some(timestamp) = args.timestamp {
+            Utc.timestamp(timestamp, 0)
+        } else {
+            match sniper.droptime(name).await? {
+                DroptimeData::Available(droptime) => droptime,
+                DroptimeData::Unavailable(error) => {
+                    print_droptime_error(name, &error)?;
+                    continue;
+                }
+            }
+        };
+        let job = SnipeJob {
+            name: name.to_string(),
+            droptime,
+            offset: config.offset,
+            spread: config.spread.clone(),
+            spin_wait: config.spin_wait,
+            lead_time: config.lead_time,
+            task: config.mode,
+            skew: Duration::zero(),
+        };
+        print_schedule(&job, &job.schedule(&overrides)?, &labels, args.json)?;
+    }
+    Ok(())
+}
+
+/// Prints the schedule of a dry run as a table or as a line of JSON.
+fn print_schedule(
+    job: &SnipeJob,
+    schedule: &Schedule,
+    labels: &[String],
+    json: bool,
+) -> Result<()> {
+    let (name, droptime) = (&job.name, job.droptime);
+    let barrier = schedule.barrier != 0;
+    if json {
+        let requests: Vec<_> = schedule
+            .requests
+            .iter()
+            .map(|request| {
+                json!({
+                    "account": labels[request.account_idx],
+                    "request": request.request_idx + 1,
+                    "connect_time": request.connect_time,
+                    "send_time": request.send_time,
+                    "relative": clock::to_millis(request.send_time - droptime),
+                })
+            })
+            .collect();
+        let schedule = json!({
+            "name": name,
+            "droptime": droptime,
+            "offset": job.offset,
+            "spread": job.spread,
+            "spin_wait": job.spin_wait,
+            "barrier": barrier,
+            "requests": requests,
+        });
+        writeln!(stdout(), "{schedule}")?;
+        return Ok(());
+    }
+    writeln!(
+        stdout(),
+        "Dry run of {name} at {} with an offset of {} ms and a spread of {} ({})",
+        droptime.with_timezone(&Local).format("%F %T"),
+        job.offset,
+        job.spread,
+        if barrier {
+            "responses are read once every request is sent"
+        } else {
+            "no barrier"
+        }
+    )?;
+    writeln!(
+        stdout(),
+        "{:<32}{:>8}{:>16}{:>16}{:>12}",
+        "account",
+        "request",
+        "connect",
+        "send",
+        "relative"
+    )?;
+    for request in &schedule.requests {
+        writeln!(
+            stdout(),
+            "{:<32}{:>8}{:>16}{:>16}{:>10.0}ms",
+            labels[request.account_idx],
+            request.request_idx + 1,
+            request
+                .connect_time
+                .with_timezone(&Local)
+                .format("%T%.3f")
+                .to_string(),
+            request
+                .send_time
+                .with_timezone(&Local)
+                .format("%T%.3f")
+                .to_string(),
+            clock::to_millis(request.send_time - droptime)
+        )?;
+    }
+    Ok(())
+}
+
+fn vault(path: &Path, command: &cli::VaultCommand) -> Result<()> {
+    let exists = path.exists();
+    if !exists && !matches!(command, cli::VaultCommand::Add { .. }) {
+        bail!(
+            "There is no vault at {}, add an account to create one",
+            path.display()
+        );
+    }
+    let passphrase = cli::get_vault_passphrase(!exists)?;
+    let mut vault = if exists {
+        Vault::open(path, &passphrase)?
+    } else {
+        Vault::create(path, &passphrase)
+    };
+    match command {
+        cli::VaultCommand::Add {
+            label,
+            bearer,
+            sq,
+            device_code,
+        } => {
+            if vault.get(label).is_some() {
+                bail!("The vault already has an account labelled {label}");
+            }
+            vault.add(cli::get_vault_account(
+                label.clone(),
+                *bearer,
+                *sq,
+                *device_code,
+            )?)?;
+            vault.save()?;
+            writeln!(stdout(), "Added {label} to {}", path.display())?;
+        }
+        cli::VaultCommand::List => {
+            for account in vault.accounts() {
+                writeln!(
+                    stdout(),
+                    "{:<24}{}",
+                    account.label,
+                    account.email.as_deref().unwrap_or("bearer token")
+                )?;
+            }
+            writeln!(stdout(), "{} account(s)", vault.accounts().len())?;
+        }
+        cli::VaultCommand::Remove { label } => {
+            vault.remove(label)?;
+            vault.save()?;
+            writeln!(stdout(), "Removed {label} from {}", path.display())?;
+        }
+        cli::VaultCommand::Export => {
+            for account in vault.accounts() {
+                let mut entry = toml::value::Table::new();
+                for (field, value) in [
+                    ("email", &account.email),
+                    ("password", &account.password),
+                    ("bearer", &account.bearer),
+                ] {
+                    if let Some(value) = value {
+                        entry.insert(field.to_string(), value.clone().into());
+                    }
+                }
+                if let Some(sq_ans) = &account.sq_ans {
+                    entry.insert("sq_ans".to_string(), sq_ans.to_vec().into());
+                }
+                writeln!(
+                    stdout(),
+                    "# {}\n[[account_entry]]\n{}",
+                    account.label,
+                    toml::to_string(&entry)?
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_account_count(config: &Config) -> Result<()> {
+    if config.name_queue.is_none() || !config.name_queue.clone().unwrap().never_stop_sniping {
+        if config.mode != SnipeTask::Giftcode && config.account_entry.len() > 1 {
+            bail!("Unable to use more than one normal account");
+        } else if config.account_entry.len() > 10 {
+            bail!("Unable to use more than 10 prename accounts");
+        }
+    }
+    Ok(())
+}
+
+/// Checks everything a snipe depends on without sniping, and fails if anything would stop
+/// the snipe from going ahead.
+async fn check(args: &cli::Args, config: &Config) -> Result<()> {
+    let mut problems = 0;
+    report(
+        &format!("Config {}", args.config.display()),
+        check_account_count(config).map(|()| format!("{} account(s)", config.account_entry.len())),
+        &mut problems,
+    )?;
+    let sniper = Sniper::new(&config.endpoints)?;
+    let mut authenticator = Authenticator::new(&sniper, config, &print_auth_event)?;
+    for (idx, account) in config.account_entry.iter().enumerate() {
+        if account.bearer.is_none() && idx != 0 {
+            authenticator.rate_limit_wait().await?;
+        }
+        report(
+            &format!("Account {}", account.label(idx)),
+            check_account(&sniper, &mut authenticator, account, config.mode).await,
+            &mut problems,
+        )?;
+    }
+    authenticator.save()?;
+    if let Some(skin) = &config.skin {
+        report(
+            "Skin",
+            skin.validate().map(|()| skin.path.clone()),
+            &mut problems,
+        )?;
+    }
+    let name_list = if let Some(name) = &args.name {
+        vec![name.clone()]
+    } else if let Some(name_queue) = &config.name_queue {
+        name_queue.queue.clone()
+    } else {
+        writeln!(stdout(), "No names queued, skipping droptime checks")?;
+        Vec::new()
+    };
+    for name in name_list {
+        let name = name.trim();
+        report(
+            &format!("Droptime of {name}"),
+            check_droptime(&sniper, name, args.timestamp).await,
+            &mut problems,
+        )?;
+    }
+    if problems != 0 {
+        bail!("Found {problems} problem(s), see above");
+    }
+    writeln!(stdout(), "{}", Green.paint("Ready to snipe"))?;
+    Ok(())
+}
+
+/// Prints the outcome of a single readiness check, counting failures in `problems`.
+fn report(item: &str, result: Result<String>, problems: &mut usize) -> Result<()> {
+    match result {
+        Ok(detail) => writeln!(stdout(), "[{}] {item}: {detail}", Green.paint("ok"))?,
+        Err(error) => {
+            *problems += 1;
+            writeln!(stdout(), "[{}] {item}: {error:#}", Red.paint("fail"))?;
+        }
+    }
+    Ok(())
+}
+
+async fn check_account(
+    sniper: &Sniper,
+    authenticator: &mut Authenticator<'_>,
+    account: &Account,
+    task: SnipeTask,
+) -> Result<String> {
+    let bearer_token = authenticator.authenticate(account, true).await?;
+    let expires = tokens::expiry(&bearer_token)
+        .map(|expires| {
+            format!(
+                ", token expires at {}",
+                expires.with_timezone(&Local).format("%F %T")
+            )
+        })
+        .unwrap_or_default();
+    if task == SnipeTask::Giftcode {
+        return Ok(format!("signed in{expires}"));
+    }
+    sniper
+        .check_name_change_eligibility(&bearer_token)
+        .await
+        .with_context(|| "Failed to check name change eligibility")?;
+    Ok(format!("signed in, name change allowed{expires}"))
+}
+
+async fn check_droptime(sniper: &Sniper, name: &str, timestamp: Option<i64>) -> Result<String> {
+    let droptime = if let Some(timestamp) = timestamp {
+        Utc.timestamp(timestamp, 0)
+    } else {
+        match sniper.droptime(name).await? {
+            DroptimeData::Available(droptime) => droptime,
+            DroptimeData::Unavailable(error) => bail!(error),
+        }
+    };
+    if droptime <= Utc::now() {
+        bail!(
+            "{} has already passed",
+            droptime.with_timezone(&Local).format("%F %T")
+        );
+    }
+    Ok(droptime.with_timezone(&Local).format("%F %T").to_string())
+}
+
+/// Prints what happened while signing in.
+fn print_auth_event(event: &AuthEvent<'_>) -> Result<()> {
+    let line = match event {
+        AuthEvent::RateLimitWait => "Waiting 20 seconds to prevent rate limiting...".to_string(),
+        AuthEvent::CacheIgnored(error) => Red
+            .paint(format!("Ignoring the token cache: {error:#}"))
+            .to_string(),
+        AuthEvent::CacheNotSaved(error) => Red
+            .paint(format!("Failed to cache bearer tokens: {error:#}"))
+            .to_string(),
+        AuthEvent::CachedToken { email } => format!("Using the cached token of {email}"),
+        AuthEvent::DeviceCode { email, code } => format!(
+            "To sign in to {email}, open {} and enter the code {} within {} minutes",
+            Cyan.paint(&code.verification_uri),
+            Green.bold().paint(&code.user_code),
+            code.expires_in / 60
+        ),
+        AuthEvent::RefreshTokenFailed { email, error } => Red
+            .paint(format!(
+                "Failed to refresh the sign in of {email}, signing in with a new code: {error:#}"
+            ))
+            .to_string(),
+        AuthEvent::SignInFailed { task, .. } => {
+            let account_type = if *task == SnipeTask::Mojang {
+                "Mojang"
+            } else {
+                "Microsoft"
+            };
+            Red.paint(format!(
+                "Failed to authenticate a {account_type} account, moving on to next account..."
+            ))
+            .to_string()
+        }
+        AuthEvent::NotEligible { email, .. } => Red
+            .paint(format!(
+                "Failed to check name change eligibility of {email}"
+            ))
+            .to_string(),
+        AuthEvent::BearerExpiring { label, expires } => {
+            let warning = match expires {
+                Some(expires) => format!(
+                    "Warning: the bearer token of {label} expires at {}, before the snipe. Replace it or sign in with an email and password instead",
+                    expires.with_timezone(&Local).format("%F %T")
+                ),
+                None => format!("Warning: unable to tell when the bearer token of {label} expires"),
+            };
+            Red.bold().paint(warning).to_string()
+        }
+        AuthEvent::SigningInAgain { labels, at } => format!(
+            "The tokens of {} expire before the snipe, signing in again at {}",
+            labels.join(", "),
+            at.with_timezone(&Local).format("%F %T")
+        ),
+        AuthEvent::NoTimeToSignInAgain { label } => Red
+            .bold()
+            .paint(format!(
+                "Warning: no time left to sign in to {label} again before the snipe"
+            ))
+            .to_string(),
+        AuthEvent::SignInAgainFailed { label, error } => Red
+            .bold()
+            .paint(format!(
+                "Warning: failed to sign in to {label} again: {error:#}"
+            ))
+            .to_string(),
+        AuthEvent::StillExpiring { labels } => Red
+            .bold()
+            .paint(format!(
+                "Warning: the tokens of {} expire before the snipe even after signing in again",
+                labels.join(", ")
+            ))
+            .to_string(),
+    };
+    writeln!(stdout(), "{line}")?;
+    Ok(())
+}
+
+fn print_results(res_data: &[ResData], droptime: DateTime<Utc>) -> Result<Option<usize>> {
+    let mut is_success = None;
+    for res in res_data {
+        let formatted_timestamp = res.timestamp.with_timezone(&Local).format("%F %T%.6f");
+        let jitter = res
+            .timeline
+            .sent
+            .map(|sent| format!(" (sent {:+.3} ms)", clock::to_millis(sent - res.send_time)))
+            .unwrap_or_default();
+        if res.is_success() {
+            writeln!(
+                stdout(),
+                "[{}] {} @ {}{}",
+                Green.paint("success"),
+                Green.paint("200"),
+                Cyan.paint(formatted_timestamp.to_string()),
+                jitter
+            )?;
+            is_success = Some(res.account_idx);
+        } else {
+            let reason = res
+                .response
+                .as_ref()
+                .and_then(HttpResponse::reason)
+                .map(|reason| format!(" ({reason})"))
+                .unwrap_or_default();
+            writeln!(
+                stdout(),
+                "[{}] {} @ {}{}{}",
+                Red.paint("fail"),
+                Red.paint(res.outcome.to_string()),
+                Cyan.paint(formatted_timestamp.to_string()),
+                jitter,
+                reason
+            )?;
+        }
+        print_timeline(&res.timeline, droptime)?;
     }
+    Ok(is_success)
+}
+
+/// Prints when each step of a request happened relative to the droptime.
+/// Prints a line whenever a snipe request reconnects, naming the account by `labels`.
+fn reconnect_logger(labels: Vec<String>, droptime: DateTime<Utc>) -> ReconnectHandler {
+    Arc::new(move |account_idx, request_idx, reconnect: &Reconnect| {
+        let _ = writeln!(
+            stdout(),
+            "{} request {} of {} at {:+.1}ms: {}",
+            Red.paint("Reconnecting"),
+            request_idx + 1,
+            labels[account_idx],
+            clock::to_millis(reconnect.at - droptime),
+            reconnect.reason
+        );
+    })
+}
+
+fn print_timeline(timeline: &Timeline, droptime: DateTime<Utc>) -> Result<()> {
+    let relative = |time: Option<DateTime<Utc>>| {
+        time.map_or_else(
+            || "-".to_string(),
+            |time| format!("{:+.1}ms", clock::to_millis(time - droptime)),
+        )
+    };
+    let handshake = timeline.handshake().map_or_else(
+        || "-".to_string(),
+        |handshake| format!("{:.1}ms", clock::to_millis(handshake)),
+    );
+    writeln!(
+        stdout(),
+        "    connect {}, connected {} (TLS {}), head {}, sent {}, first byte {}",
+        relative(timeline.connect_start),
+        relative(timeline.connected),
+        handshake,
+        relative(timeline.head_written),
+        relative(timeline.sent),
+        relative(timeline.first_byte)
+    )?;
+    for reconnect in &timeline.reconnects {
+        writeln!(
+            stdout(),
+            "    {} {}: {}",
+            Red.paint("reconnected"),
+            relative(Some(reconnect.at)),
+            reconnect.reason
+        )?;
+    }
+    Ok(())
+}
+
+/// Returns the skew the snipe time should be corrected by.
+async fn check_clock(sniper: &Sniper, clock_check: &ClockCheck) -> Result<Duration> {
+    let skew = sniper.check_clock(clock_check).await?;
+    writeln!(stdout(), "{skew}")?;
+    let skew_ms = clock::to_millis(skew.skew).abs();
+    let uncertainty_ms = clock::to_millis(skew.uncertainty);
+    if skew_ms - uncertainty_ms > f64::from(clock_check.max_skew) {
+        bail!(
+            "Clock skew of at least {:.1} ms exceeds the maximum of {} ms, sync your clock before sniping",
+            skew_ms - uncertainty_ms,
+            clock_check.max_skew
+        );
+    }
+    if !clock_check.correct {
+        return Ok(Duration::zero());
+    }
+    if uncertainty_ms >= skew_ms {
+        writeln!(
+            stdout(),
+            "{}",
+            Red.paint(
+                "Not correcting the snipe time as the skew is within the measurement's uncertainty"
+            )
+        )?;
+        return Ok(Duration::zero());
+    }
+    writeln!(
+        stdout(),
+        "Correcting snipe time by {:.1} ms",
+        clock::to_millis(skew.skew)
+    )?;
+    Ok(skew.skew)
+}
+
+async fn calibrate(config: &Config, path: &Path, samples: usize, write: bool) -> Result<()> {
+    if samples == 0 {
+        bail!("At least one sample is required");
+    }
+    writeln!(
+        stdout(),
+        "Measuring {samples} connections to {}...",
+        config.endpoints.minecraft_services
+    )?;
+    let measurements = calibrate::measure(&config.endpoints, samples)
+        .await
+        .with_context(|| "Failed to measure connection timings")?;
+    let calibration = calibrate::recommend(&measurements);
+    writeln!(
+        stdout(),
+        "{:<12}{:>10}{:>10}{:>10}{:>10}{:>10}",
+        "",
+        "min",
+        "median",
+        "mean",
+        "max",
+        "stddev"
+    )?;
+    for (label, stats) in [
+        ("connect", &calibration.connect),
+        ("handshake", &calibration.handshake),
+        ("round trip", &calibration.round_trip),
+    ] {
+        print_stats(label, stats)?;
+    }
+    let (lower, upper) = calibration.interval;
+    writeln!(
+        stdout(),
+        "{}",
+        Green.paint(format!(
+            "Recommended offset: {} ms (estimate {:.2} ms, 95% CI {lower:.2}-{upper:.2} ms)",
+            calibration.offset,
+            calibration.round_trip.median / 2.0
+        ))
+    )?;
+    if write {
+        config::write_offset(path, calibration.offset)
+            .with_context(|| format!("Failed to write offset to {}", path.display()))?;
+        writeln!(
+            stdout(),
+            "Wrote offset = {} to {}",
+            calibration.offset,
+            path.display()
+        )?;
+    }
+    Ok(())
+}
+
+fn print_stats(label: &str, stats: &Stats) -> Result<()> {
+    writeln!(
+        stdout(),
+        "{label:<12}{:>10.2}{:>10.2}{:>10.2}{:>10.2}{:>10.2}",
+        stats.min,
+        stats.median,
+        stats.mean,
+        stats.max,
+        stats.stddev
+    )?;
+    Ok(())
+}
+
+fn stats(config: &Config, last: Option<usize>, all_endpoints: bool) -> Result<()> {
+    let mut records = history::read(Path::new(&config.history_file))
+        .with_context(|| format!("Failed to read snipe history from {}", config.history_file))?;
+    if !all_endpoints {
+        let endpoint = &config.endpoints.minecraft_services;
+        let count = records.len();
+        records.retain(|record| &record.endpoint == endpoint);
+        if records.len() != count {
+            writeln!(
+                stdout(),
+                "Ignoring {} snipes sent to other endpoints than {endpoint}, pass --all-endpoints to include them",
+                count - records.len()
+            )?;
+        }
+    }
+    if let Some(last) = last {
+        records.drain(..records.len().saturating_sub(last));
+    }
+    if records.is_empty() {
+        bail!("No snipes recorded in {}", config.history_file);
+    }
+    let analysis = analysis::analyse(&records);
+    writeln!(
+        stdout(),
+        "{} snipes recorded, {} successful",
+        analysis.snipes,
+        analysis.successes
+    )?;
+    print_breakdown("offset", &analysis.by_offset)?;
+    print_breakdown("spread", &analysis.by_spread)?;
+    let Some(boundary) = analysis.boundary else {
+        writeln!(
+            stdout(),
+            "{}",
+            Red.paint("No successful snipes to estimate the acceptance boundary from")
+        )?;
+        return Ok(());
+    };
+    let rejected = boundary
+        .rejected
+        .map_or_else(|| "-".to_string(), |rejected| format!("{rejected:+.1}ms"));
+    writeln!(
+        stdout(),
+        "Latest early rejection {rejected}, earliest acceptance {:+.1}ms, boundary {:+.1}ms (from {} successful snipes)",
+        boundary.accepted,
+        boundary.estimate,
+        boundary.samples
+    )?;
+    if let Some(offset) = analysis.offset {
+        writeln!(
+            stdout(),
+            "{}",
+            Green.paint(format!("Recommended offset: {offset} ms"))
+        )?;
+    }
+    Ok(())
+}
+
+fn print_breakdown(label: &str, breakdown: &[Breakdown<impl Display>]) -> Result<()> {
+    writeln!(
+        stdout(),
+        "{label:<32}{:>10}{:>10}{:>10}",
+        "snipes",
+        "success",
+        "rate"
+    )?;
+    for group in breakdown {
+        writeln!(
+            stdout(),
+            "{:<32}{:>10}{:>10}{:>9.0}%",
+            group.value.to_string(),
+            group.snipes,
+            group.successes,
+            group.rate()
+        )?;
+    }
+    Ok(())
+}
+
+fn print_droptime_error(name: &str, error: &str) -> Result<()> {
+    writeln!(
+        stdout(),
+        "{}",
+        Red.paint(format!("Failed to get the droptime of {name}: {error}"))
+    )?;
     Ok(())
 }
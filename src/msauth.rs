@@ -1,15 +1,20 @@
+use crate::config::Endpoints;
 use anyhow::{anyhow, bail, Context, Result};
-use lazy_static::lazy_static;
 use regex::Regex;
-use reqwest::{blocking::Client, header::ACCEPT};
+use reqwest::{header::ACCEPT, Client};
 use serde::Deserialize;
 use serde_json::json;
-use std::{collections::HashMap, time::Duration};
+use std::{collections::HashMap, sync::LazyLock, time::Duration};
+use tokio::time::{sleep, Instant};
+
+const CLIENT_ID: &str = "000000004C12AE6F";
+const SCOPE: &str = "service::user.auth.xboxlive.com::MBI_SSL";
 
 pub struct Auth<'a> {
     client: Client,
     email: &'a str,
     password: &'a str,
+    endpoints: &'a Endpoints,
 }
 
 struct LoginData {
@@ -47,45 +52,50 @@ struct BearerToken {
 }
 
 impl<'a> Auth<'a> {
-    pub fn new(email: &'a str, password: &'a str) -> Result<Self> {
+    pub fn new(email: &'a str, password: &'a str, endpoints: &'a Endpoints) -> Result<Self> {
         let client = Client::builder()
             .cookie_store(true)
             .timeout(Duration::from_secs(5))
+            .danger_accept_invalid_certs(endpoints.accept_invalid_certs)
             .build()?;
         Ok(Self {
             client,
             email,
             password,
+            endpoints,
         })
     }
 
-    pub fn authenticate(&self) -> Result<String> {
+    pub async fn authenticate(&self) -> Result<String> {
         let access_token = self
             .get_access_token()
+            .await
             .with_context(|| "Unable to get access token")?;
-        let bearer_token = self
-            .get_bearer_token(&access_token)
+        let bearer_token = get_bearer_token(&self.client, self.endpoints, &access_token)
+            .await
             .with_context(|| "Unable to get bearer token")?;
         Ok(bearer_token)
     }
 
-    fn get_access_token(&self) -> Result<String> {
+    async fn get_access_token(&self) -> Result<String> {
         let login_data = self
             .get_login_data()
+            .await
             .with_context(|| "Unable to get login data")?;
         let access_token = self
             .sign_in(&login_data)
+            .await
             .with_context(|| "Unable to get access token")?;
         Ok(access_token)
     }
 
-    fn get_login_data(&self) -> Result<LoginData> {
-        lazy_static! {
-            static ref PPFT_RE: Regex = Regex::new(r#"value="(.+?)""#).unwrap();
-            static ref URLPOST_RE: Regex = Regex::new("urlPost:'(.+?)'").unwrap();
-        }
-        let res = self.client.get("https://login.live.com/oauth20_authorize.srf?client_id=000000004C12AE6F&redirect_uri=https://login.live.com/oauth20_desktop.srf&scope=service::user.auth.xboxlive.com::MBI_SSL&display=touch&response_type=token&locale=en").send()?;
-        let html = res.text()?;
+    async fn get_login_data(&self) -> Result<LoginData> {
+        static PPFT_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"value="(.+?)""#).unwrap());
+        static URLPOST_RE: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new("urlPost:'(.+?)'").unwrap());
+        let live_login = &self.endpoints.live_login;
+        let res = self.client.get(format!("{live_login}/oauth20_authorize.srf?client_id=000000004C12AE6F&redirect_uri={live_login}/oauth20_desktop.srf&scope=service::user.auth.xboxlive.com::MBI_SSL&display=touch&response_type=token&locale=en")).send().await?;
+        let html = res.text().await?;
         let ppft_captures = PPFT_RE
             .captures(&html)
             .ok_or_else(|| anyhow!("Unable to capture PPFT from regex"))?;
@@ -105,7 +115,7 @@ impl<'a> Auth<'a> {
         Ok(LoginData { ppft, url_post })
     }
 
-    fn sign_in(&self, login_data: &LoginData) -> Result<String> {
+    async fn sign_in(&self, login_data: &LoginData) -> Result<String> {
         let params = [
             ("login", self.email),
             ("loginfmt", self.email),
@@ -116,19 +126,20 @@ impl<'a> Auth<'a> {
             .client
             .post(&login_data.url_post)
             .form(&params)
-            .send()?;
+            .send()
+            .await?;
         let status = res.status();
         if status.as_u16() != 200 {
-            bail!("HTTP {}", status);
+            bail!("HTTP {status}");
         }
         let url = res.url().clone();
-        let text = res.text()?;
+        let text = res.text().await?;
         if !url.to_string().contains("access_token") && url.as_str() == login_data.url_post {
             if text.contains("Sign in to") {
                 bail!("Incorrect credentials");
             }
             if text.contains("2FA is enabled but not supported yet!") {
-                bail!("Please disable 2FA at https://account.live.com/activity");
+                bail!("2FA is enabled, sign in with `device_code = true` instead or disable 2FA at https://account.live.com/activity");
             }
         }
         let mut param: HashMap<&str, &str> = url
@@ -145,94 +156,257 @@ impl<'a> Auth<'a> {
             .ok_or_else(|| anyhow!("Unable to extract access_token from redirect URL"))?
             .to_string())
     }
+}
 
-    fn get_bearer_token(&self, access_token: &str) -> Result<String> {
-        let xbl_data = self
-            .authenticate_with_xbl(access_token)
-            .with_context(|| "Unable to get Xbox Live data")?;
-        let xsts_token = self
-            .authenticate_with_xsts(&xbl_data.token)
-            .with_context(|| "Unable to get XSTS token")?;
-        let bearer_token = self
-            .authenticate_with_minecraft(&xbl_data.display_claims.xui[0].uhs, &xsts_token)
-            .with_context(|| "Unable to get bearer token")?;
-        Ok(bearer_token)
+/// A code the user enters at `verification_uri` to sign in.
+#[derive(Deserialize)]
+pub struct DeviceCode {
+    pub user_code: String,
+    #[serde(rename = "device_code")]
+    code: String,
+    pub verification_uri: String,
+    /// Seconds until the code expires.
+    pub expires_in: u64,
+    interval: u64,
+}
+
+#[derive(Deserialize)]
+struct LiveToken {
+    access_token: String,
+    refresh_token: String,
+}
+
+#[derive(Deserialize)]
+struct LiveError {
+    error: String,
+}
+
+/// Tokens of a completed device code sign in.
+pub struct DeviceSignIn {
+    pub bearer_token: String,
+    /// Signs in again without entering a code.
+    pub refresh_token: String,
+}
+
+/// Signs in through the OAuth device code flow, which unlike the login form works with
+/// two-factor authentication.
+pub struct DeviceCodeAuth<'a> {
+    client: Client,
+    endpoints: &'a Endpoints,
+}
+
+impl<'a> DeviceCodeAuth<'a> {
+    pub fn new(endpoints: &'a Endpoints) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(5))
+            .danger_accept_invalid_certs(endpoints.accept_invalid_certs)
+            .build()?;
+        Ok(Self { client, endpoints })
     }
 
-    fn authenticate_with_xbl(&self, access_token: &str) -> Result<AuthData> {
-        let json = json!({
-            "Properties": {
-                "AuthMethod": "RPS",
-                "SiteName": "user.auth.xboxlive.com",
-                "RpsTicket": access_token
-            },
-            "RelyingParty": "http://auth.xboxlive.com",
-            "TokenType": "JWT"
-        });
+    pub async fn request_code(&self) -> Result<DeviceCode> {
         let res = self
             .client
-            .post("https://user.auth.xboxlive.com/user/authenticate")
-            .json(&json)
-            .header(ACCEPT, "application/json")
-            .send()?;
+            .post(format!("{}/oauth20_connect.srf", self.endpoints.live_login))
+            .form(&[
+                ("client_id", CLIENT_ID),
+                ("scope", SCOPE),
+                ("response_type", "device_code"),
+            ])
+            .send()
+            .await?;
         let status = res.status();
         if status.as_u16() != 200 {
-            bail!("HTTP {}", status);
+            bail!("HTTP {status}");
         }
-        let auth_data: AuthData = serde_json::from_str(&res.text()?)?;
-        Ok(auth_data)
+        Ok(serde_json::from_str(&res.text().await?)?)
     }
 
-    fn authenticate_with_xsts(&self, token: &str) -> Result<String> {
-        let json = json!({
-            "Properties": {
-                "SandboxId": "RETAIL",
-                "UserTokens": [token]
-            },
-            "RelyingParty": "rp://api.minecraftservices.com/",
-            "TokenType": "JWT"
-        });
-        let res = self
-            .client
-            .post("https://xsts.auth.xboxlive.com/xsts/authorize")
-            .header(ACCEPT, "application/json")
-            .json(&json)
-            .send()?;
-        let status = res.status();
-        let text = res.text()?;
-        match status.as_u16() {
-            401 => {
-                let err: FailedAuthData = serde_json::from_str(&text)?;
-                if err.x_err == 2_148_916_233 {
-                    bail!("This account doesn't have an Xbox account");
-                }
-                if err.x_err == 2_148_916_238 {
-                    bail!("The account is a child (under 18) and cannot proceed unless the account is added to a family by an adult");
-                }
-                bail!("Something went wrong: XErr: {}", err.x_err);
+    /// Waits for the user to enter `code` and exchanges the sign in for a bearer token.
+    pub async fn wait_for_code(&self, code: &DeviceCode) -> Result<DeviceSignIn> {
+        let deadline = Instant::now() + Duration::from_secs(code.expires_in);
+        let mut interval = Duration::from_secs(code.interval.max(1));
+        loop {
+            if Instant::now() >= deadline {
+                bail!("The code expired before it was entered");
             }
-            200 => {
-                let auth_data: AuthData = serde_json::from_str(&text)?;
-                Ok(auth_data.token)
-            }
-            _ => {
-                bail!("HTTP {}", status);
+            sleep(interval).await;
+            let token = self
+                .request_token(&[
+                    ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+                    ("device_code", &code.code),
+                ])
+                .await?;
+            match token {
+                Ok(token) => return self.sign_in(token).await,
+                Err(error) => match error.as_str() {
+                    "authorization_pending" => (),
+                    "slow_down" => interval += Duration::from_secs(5),
+                    "authorization_declined" => bail!("The sign in was declined"),
+                    "expired_token" => bail!("The code expired before it was entered"),
+                    _ => bail!("Sign in failed: {error}"),
+                },
             }
         }
     }
 
-    fn authenticate_with_minecraft(&self, userhash: &str, xsts_token: &str) -> Result<String> {
-        let json = json!({ "identityToken": format!("XBL3.0 x={};{}", userhash, xsts_token) });
+    /// Signs in again with the refresh token of an earlier sign in.
+    pub async fn refresh(&self, refresh_token: &str) -> Result<DeviceSignIn> {
+        let token = self
+            .request_token(&[
+                ("grant_type", "refresh_token"),
+                ("refresh_token", refresh_token),
+                ("scope", SCOPE),
+            ])
+            .await?;
+        match token {
+            Ok(token) => self.sign_in(token).await,
+            Err(error) => bail!("Refreshing the sign in failed: {error}"),
+        }
+    }
+
+    /// Requests a Microsoft access token, returning the OAuth error code if the request is
+    /// refused.
+    async fn request_token(&self, params: &[(&str, &str)]) -> Result<Result<LiveToken, String>> {
+        let mut form = vec![("client_id", CLIENT_ID)];
+        form.extend_from_slice(params);
         let res = self
             .client
-            .post("https://api.minecraftservices.com/authentication/login_with_xbox")
-            .json(&json)
-            .send()?;
+            .post(format!("{}/oauth20_token.srf", self.endpoints.live_login))
+            .form(&form)
+            .send()
+            .await?;
         let status = res.status();
-        if status.as_u16() != 200 {
-            bail!("HTTP {}", status);
+        let text = res.text().await?;
+        match status.as_u16() {
+            200 => Ok(Ok(serde_json::from_str(&text)?)),
+            400 | 401 => Ok(Err(serde_json::from_str::<LiveError>(&text)?.error)),
+            _ => bail!("HTTP {status}"),
+        }
+    }
+
+    async fn sign_in(&self, token: LiveToken) -> Result<DeviceSignIn> {
+        let bearer_token = get_bearer_token(&self.client, self.endpoints, &token.access_token)
+            .await
+            .with_context(|| "Unable to get bearer token")?;
+        Ok(DeviceSignIn {
+            bearer_token,
+            refresh_token: token.refresh_token,
+        })
+    }
+}
+
+async fn get_bearer_token(
+    client: &Client,
+    endpoints: &Endpoints,
+    access_token: &str,
+) -> Result<String> {
+    let xbl_data = authenticate_with_xbl(client, endpoints, access_token)
+        .await
+        .with_context(|| "Unable to get Xbox Live data")?;
+    let xsts_token = authenticate_with_xsts(client, endpoints, &xbl_data.token)
+        .await
+        .with_context(|| "Unable to get XSTS token")?;
+    let bearer_token = authenticate_with_minecraft(
+        client,
+        endpoints,
+        &xbl_data.display_claims.xui[0].uhs,
+        &xsts_token,
+    )
+    .await
+    .with_context(|| "Unable to get bearer token")?;
+    Ok(bearer_token)
+}
+
+async fn authenticate_with_xbl(
+    client: &Client,
+    endpoints: &Endpoints,
+    access_token: &str,
+) -> Result<AuthData> {
+    let json = json!({
+        "Properties": {
+            "AuthMethod": "RPS",
+            "SiteName": "user.auth.xboxlive.com",
+            "RpsTicket": access_token
+        },
+        "RelyingParty": "http://auth.xboxlive.com",
+        "TokenType": "JWT"
+    });
+    let res = client
+        .post(format!("{}/user/authenticate", endpoints.xbl))
+        .json(&json)
+        .header(ACCEPT, "application/json")
+        .send()
+        .await?;
+    let status = res.status();
+    if status.as_u16() != 200 {
+        bail!("HTTP {status}");
+    }
+    let auth_data: AuthData = serde_json::from_str(&res.text().await?)?;
+    Ok(auth_data)
+}
+
+async fn authenticate_with_xsts(
+    client: &Client,
+    endpoints: &Endpoints,
+    token: &str,
+) -> Result<String> {
+    let json = json!({
+        "Properties": {
+            "SandboxId": "RETAIL",
+            "UserTokens": [token]
+        },
+        "RelyingParty": "rp://api.minecraftservices.com/",
+        "TokenType": "JWT"
+    });
+    let res = client
+        .post(format!("{}/xsts/authorize", endpoints.xsts))
+        .header(ACCEPT, "application/json")
+        .json(&json)
+        .send()
+        .await?;
+    let status = res.status();
+    let text = res.text().await?;
+    match status.as_u16() {
+        401 => {
+            let err: FailedAuthData = serde_json::from_str(&text)?;
+            if err.x_err == 2_148_916_233 {
+                bail!("This account doesn't have an Xbox account");
+            }
+            if err.x_err == 2_148_916_238 {
+                bail!("The account is a child (under 18) and cannot proceed unless the account is added to a family by an adult");
+            }
+            bail!("Something went wrong: XErr: {}", err.x_err);
+        }
+        200 => {
+            let auth_data: AuthData = serde_json::from_str(&text)?;
+            Ok(auth_data.token)
+        }
+        _ => {
+            bail!("HTTP {status}");
         }
-        let bearer_token: BearerToken = serde_json::from_str(&res.text()?)?;
-        Ok(bearer_token.access_token)
     }
 }
+
+async fn authenticate_with_minecraft(
+    client: &Client,
+    endpoints: &Endpoints,
+    userhash: &str,
+    xsts_token: &str,
+) -> Result<String> {
+    let json = json!({ "identityToken": format!("XBL3.0 x={userhash};{xsts_token}") });
+    let res = client
+        .post(format!(
+            "{}/authentication/login_with_xbox",
+            endpoints.minecraft_services
+        ))
+        .json(&json)
+        .send()
+        .await?;
+    let status = res.status();
+    if status.as_u16() != 200 {
+        bail!("HTTP {status}");
+    }
+    let bearer_token: BearerToken = serde_json::from_str(&res.text().await?)?;
+    Ok(bearer_token.access_token)
+}
@@ -1,16 +1,21 @@
+use crate::config::Endpoints;
 use anyhow::{bail, Context, Result};
-use chrono::{DateTime, Local, TimeZone};
-use reqwest::blocking::{multipart::Form, Client};
+use chrono::{DateTime, TimeZone, Utc};
+use reqwest::{
+    multipart::{Form, Part},
+    Client,
+};
 use serde::Deserialize;
 use serde_json::json;
-use std::time::Duration;
+use std::{path::Path, time::Duration};
 
 pub struct Requests {
     client: Client,
+    endpoints: Endpoints,
 }
 
 pub enum DroptimeData {
-    Available(DateTime<Local>),
+    Available(DateTime<Utc>),
     Unavailable(String),
 }
 
@@ -47,31 +52,36 @@ pub struct QuestionID {
 }
 
 impl Requests {
-    pub fn new() -> Result<Self> {
+    pub fn new(endpoints: &Endpoints) -> Result<Self> {
         Ok(Self {
             client: Client::builder()
                 .timeout(Duration::from_secs(5))
                 .user_agent("Sniper")
+                .danger_accept_invalid_certs(endpoints.accept_invalid_certs)
                 .build()?,
+            endpoints: endpoints.clone(),
         })
     }
 
-    pub fn authenticate_mojang(
+    pub async fn authenticate_mojang(
         &self,
         email: &str,
         password: &str,
-        answers: &Option<[String; 3]>,
+        answers: Option<&[String; 3]>,
     ) -> Result<String> {
         let bearer_token = self
             .get_bearer_token(email, password)
+            .await
             .with_context(|| "Error getting bearer token")?;
         if let Some(questions) = self
             .get_questions(&bearer_token)
+            .await
             .with_context(|| "Failed to get SQ IDs")?
         {
             match answers {
                 Some(x) => {
                     self.send_answers(&bearer_token, &questions, x)
+                        .await
                         .with_context(|| "Failed to send SQ answers")?;
                 }
                 None => {
@@ -82,42 +92,47 @@ impl Requests {
         Ok(bearer_token)
     }
 
-    fn get_bearer_token(&self, email: &str, password: &str) -> Result<String> {
+    async fn get_bearer_token(&self, email: &str, password: &str) -> Result<String> {
         let post_json = json!({
             "username": email,
             "password": password
         });
         let res = self
             .client
-            .post("https://authserver.mojang.com/authenticate")
+            .post(format!("{}/authenticate", self.endpoints.mojang_auth))
             .json(&post_json)
-            .send()?;
+            .send()
+            .await?;
         let status = res.status();
         match status.as_u16() {
             200 => {
-                let bearer_token: BearerToken = serde_json::from_str(&res.text()?)?;
+                let bearer_token: BearerToken = serde_json::from_str(&res.text().await?)?;
                 Ok(bearer_token.access_token)
             }
             403 => {
                 bail!("Incorrect email or password");
             }
             _ => {
-                bail!("HTTP {}", status);
+                bail!("HTTP {status}");
             }
         }
     }
 
-    fn get_questions(&self, bearer_token: &str) -> Result<Option<[QuestionData; 3]>> {
+    async fn get_questions(&self, bearer_token: &str) -> Result<Option<[QuestionData; 3]>> {
         let res = self
             .client
-            .get("https://api.mojang.com/user/security/challenges")
+            .get(format!(
+                "{}/user/security/challenges",
+                self.endpoints.mojang_api
+            ))
             .bearer_auth(bearer_token)
-            .send()?;
+            .send()
+            .await?;
         let status = res.status();
         if status.as_u16() != 200 {
-            bail!("HTTP {}", status);
+            bail!("HTTP {status}");
         }
-        let body = res.text()?;
+        let body = res.text().await?;
         if body == "[]" {
             Ok(None)
         } else {
@@ -126,7 +141,7 @@ impl Requests {
         }
     }
 
-    fn send_answers(
+    async fn send_answers(
         &self,
         bearer_token: &str,
         questions: &[QuestionData; 3],
@@ -148,48 +163,56 @@ impl Requests {
         ]);
         let res = self
             .client
-            .post("https://api.mojang.com/user/security/location")
+            .post(format!(
+                "{}/user/security/location",
+                self.endpoints.mojang_api
+            ))
             .bearer_auth(bearer_token)
             .json(&post_body)
-            .send()?;
+            .send()
+            .await?;
         let status = res.status();
         match status.as_u16() {
             204 => Ok(()),
             403 => bail!("Incorrect security questions"),
-            _ => bail!("HTTP {}", status),
+            _ => bail!("HTTP {status}"),
         }
     }
 
-    pub fn check_name_availability_time(&self, name: &str) -> Result<DroptimeData> {
-        let url = format!("http://api.star.shopping/droptime/{}", name);
-        let res = self.client.get(url).send()?;
+    pub async fn check_name_availability_time(&self, name: &str) -> Result<DroptimeData> {
+        let url = format!("{}/droptime/{name}", self.endpoints.droptime);
+        let res = self.client.get(url).send().await?;
         let status = res.status();
-        let body = res.text()?;
+        let body = res.text().await?;
         match status.as_u16() {
             200 => {
                 let epoch: AvailableDroptime = serde_json::from_str(&body)?;
-                let droptime = Local.timestamp(epoch.unix, 0);
+                let droptime = Utc.timestamp(epoch.unix, 0);
                 Ok(DroptimeData::Available(droptime))
             }
             400 => {
                 let error: UnavailableDroptime = serde_json::from_str(&body)?;
                 Ok(DroptimeData::Unavailable(error.error))
             }
-            _ => bail!("HTTP {}", status),
+            _ => bail!("HTTP {status}"),
         }
     }
 
-    pub fn check_name_change_eligibility(&self, bearer_token: &str) -> Result<()> {
+    pub async fn check_name_change_eligibility(&self, bearer_token: &str) -> Result<()> {
         let res = self
             .client
-            .get("https://api.minecraftservices.com/minecraft/profile/namechange")
+            .get(format!(
+                "{}/minecraft/profile/namechange",
+                self.endpoints.minecraft_services
+            ))
             .bearer_auth(bearer_token)
-            .send()?;
+            .send()
+            .await?;
         let status = res.status();
         if status.as_u16() != 200 {
-            bail!("HTTP {}", status);
+            bail!("HTTP {status}");
         }
-        let body = res.text()?;
+        let body = res.text().await?;
         let is_allowed: NameChangeEligibility = serde_json::from_str(&body)?;
         if !is_allowed.name_change_allowed {
             bail!("Name change not allowed within the cooldown period")
@@ -197,7 +220,7 @@ impl Requests {
         Ok(())
     }
 
-    pub fn upload_skin(
+    pub async fn upload_skin(
         &self,
         bearer_token: &str,
         path: &str,
@@ -206,10 +229,23 @@ impl Requests {
     ) -> Result<()> {
         let res = self
             .client
-            .post("https://api.minecraftservices.com/minecraft/profile/skins")
+            .post(format!(
+                "{}/minecraft/profile/skins",
+                self.endpoints.minecraft_services
+            ))
             .bearer_auth(bearer_token);
         let res = if is_file {
-            let form = Form::new().text("variant", skin_model).file("file", path)?;
+            let skin = tokio::fs::read(path)
+                .await
+                .with_context(|| format!("Failed to read {path}"))?;
+            let file_name = Path::new(path).file_name().map_or_else(
+                || "skin.png".to_string(),
+                |name| name.to_string_lossy().to_string(),
+            );
+            let part = Part::bytes(skin)
+                .file_name(file_name)
+                .mime_str("image/png")?;
+            let form = Form::new().text("variant", skin_model).part("file", part);
             res.multipart(form)
         } else {
             let post_body = json!({
@@ -218,10 +254,10 @@ impl Requests {
             });
             res.json(&post_body)
         };
-        let res = res.send()?;
+        let res = res.send().await?;
         let status = res.status();
         if status.as_u16() != 200 {
-            bail!("HTTP {}", status);
+            bail!("HTTP {status}");
         }
         Ok(())
     }
@@ -0,0 +1,283 @@
+use crate::{config::Spread, constants::BARRIER_THRESHOLD};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+use serde::Serialize;
+
+/// When a snipe request connects to the server and when its final bytes are sent.
+#[derive(Serialize)]
+pub struct PlannedRequest {
+    pub account_idx: usize,
+    /// Index of the request among those of the same account.
+    pub request_idx: usize,
+    pub connect_time: DateTime<Utc>,
+    pub send_time: DateTime<Utc>,
+}
+
+/// Every request of a snipe in the order they are spawned.
+#[derive(Serialize)]
+pub struct Schedule {
+    pub requests: Vec<PlannedRequest>,
+    /// Number of requests that wait for each other before reading their responses, or zero
+    /// if they don't wait at all.
+    pub barrier: usize,
+}
+
+/// Number of requests sent per account.
+#[must_use]
+pub fn request_count(is_gc: bool) -> usize {
+    if is_gc {
+        5
+    } else {
+        3
+    }
+}
+
+/// How the requests of a single account are sent.
+pub struct AccountSchedule {
+    /// The instant the spread of the account's requests is relative to.
+    pub snipe_time: DateTime<Utc>,
+    pub requests: usize,
+    /// Spreads out the account's requests on their own rather than as part of the shared
+    /// spread.
+    pub spread: Option<Spread>,
+}
+
+/// Plans the requests of every account, spreading their send times around each account's
+/// snipe time according to `spread`. Each request connects `lead_time` seconds before it
+/// is sent. `rng` is only used by the jitter strategy. The requests wait for each other at
+/// a barrier unless two consecutive send times are more than `BARRIER_THRESHOLD` ms apart.
+/// Fails if an explicit spread has fewer offsets than it has requests.
+pub fn plan(
+    accounts: &[AccountSchedule],
+    spread: &Spread,
+    lead_time: u32,
+    rng: &mut impl Rng,
+) -> Result<Schedule> {
+    let shared: Vec<usize> = accounts
+        .iter()
+        .filter(|account| account.spread.is_none())
+        .map(|account| account.requests)
+        .collect();
+    let mut shared = offsets(spread, &shared, rng)?.into_iter();
+    let mut requests = Vec::new();
+    for (account_idx, account) in accounts.iter().enumerate() {
+        let offsets = match &account.spread {
+            Some(spread) => offsets(spread, &[account.requests], rng)?.remove(0),
+            None => shared.next().unwrap(),
+        };
+        for (request_idx, offset) in offsets.into_iter().enumerate() {
+            let send_time = account.snipe_time + Duration::milliseconds(offset);
+            requests.push(PlannedRequest {
+                account_idx,
+                request_idx,
+                connect_time: send_time - Duration::seconds(i64::from(lead_time)),
+                send_time,
+            });
+        }
+    }
+    let mut send_times: Vec<DateTime<Utc>> =
+        requests.iter().map(|request| request.send_time).collect();
+    send_times.sort_unstable();
+    let barrier = if send_times
+        .windows(2)
+        .all(|pair| pair[1] - pair[0] <= Duration::milliseconds(i64::from(BARRIER_THRESHOLD)))
+    {
+        requests.len()
+    } else {
+        0
+    };
+    Ok(Schedule { requests, barrier })
+}
+
+/// Milliseconds relative to the snipe time of every request of accounts that send
+/// `counts` requests each.
+fn offsets(spread: &Spread, counts: &[usize], rng: &mut impl Rng) -> Result<Vec<Vec<i64>>> {
+    let total: usize = counts.iter().sum();
+    if let Spread::Explicit { offsets } = spread {
+        if offsets.len() < total {
+            bail!(
+                "An explicit spread needs {total} offsets, one per request, but has {}",
+                offsets.len()
+            );
+        }
+    }
+    let at = |idx: usize, step: u32| i64::try_from(idx).unwrap() * i64::from(step);
+    let mut idx = 0;
+    let mut offsets = Vec::with_capacity(counts.len());
+    for (account, &count) in counts.iter().enumerate() {
+        let account_offsets = (0..count)
+            .map(|request| {
+                let offset = match spread {
+                    Spread::Linear { step } => at(idx, *step),
+                    Spread::Centred { step } => {
+                        at(idx, *step) - at(total.saturating_sub(1), *step) / 2
+                    }
+                    Spread::Explicit { offsets } => offsets[idx],
+                    Spread::Groups { step, gap } => at(account, *gap) + at(request, *step),
+                    Spread::Jitter { window } => rng.gen_range(0..=i64::from(*window)),
+                };
+                idx += 1;
+                offset
+            })
+            .collect();
+        offsets.push(account_offsets);
+    }
+    Ok(offsets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::CONNECT_LEAD_TIME;
+    use chrono::TimeZone;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn snipe_time() -> DateTime<Utc> {
+        Utc.timestamp_opt(1_650_000_000, 0).unwrap()
+    }
+
+    fn account(requests: usize) -> AccountSchedule {
+        AccountSchedule {
+            snipe_time: snipe_time(),
+            requests,
+            spread: None,
+        }
+    }
+
+    fn plan_with(accounts: &[AccountSchedule], spread: &Spread) -> Schedule {
+        plan(
+            accounts,
+            spread,
+            CONNECT_LEAD_TIME,
+            &mut StdRng::seed_from_u64(0),
+        )
+        .unwrap()
+    }
+
+    /// Send times in ms relative to the snipe time, in the order the requests are spawned.
+    fn send_offsets(schedule: &Schedule) -> Vec<i64> {
+        schedule
+            .requests
+            .iter()
+            .map(|request| (request.send_time - snipe_time()).num_milliseconds())
+            .collect()
+    }
+
+    #[test]
+    fn request_counts() {
+        assert_eq!(request_count(false), 3);
+        assert_eq!(request_count(true), 5);
+        let accounts = [account(request_count(true)), account(request_count(true))];
+        let schedule = plan_with(&accounts, &Spread::default());
+        assert_eq!(schedule.requests.len(), 10);
+        for account_idx in 0..2 {
+            let request_idxs: Vec<usize> = schedule
+                .requests
+                .iter()
+                .filter(|request| request.account_idx == account_idx)
+                .map(|request| request.request_idx)
+                .collect();
+            assert_eq!(request_idxs, [0, 1, 2, 3, 4]);
+        }
+    }
+
+    #[test]
+    fn linear_matches_old_executor() {
+        // The old executor sent every request `spread` ms after the previous one, across
+        // all accounts, and connected 32 seconds before each send
+        let accounts = [account(3), account(3)];
+        let schedule = plan_with(&accounts, &Spread::Linear { step: 10 });
+        assert_eq!(send_offsets(&schedule), [0, 10, 20, 30, 40, 50]);
+        for request in &schedule.requests {
+            assert_eq!(
+                request.send_time - request.connect_time,
+                Duration::seconds(32)
+            );
+        }
+        let account_idxs: Vec<usize> = schedule
+            .requests
+            .iter()
+            .map(|request| request.account_idx)
+            .collect();
+        assert_eq!(account_idxs, [0, 0, 0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn barrier_threshold() {
+        let accounts = [account(3)];
+        let schedule = plan_with(&accounts, &Spread::Linear { step: 27 });
+        assert_eq!(schedule.barrier, 3);
+        let schedule = plan_with(&accounts, &Spread::Linear { step: 28 });
+        assert_eq!(schedule.barrier, 0);
+        let schedule = plan_with(&accounts, &Spread::Linear { step: 0 });
+        assert_eq!(schedule.barrier, 3);
+    }
+
+    #[test]
+    fn centred() {
+        let schedule = plan_with(&[account(5)], &Spread::Centred { step: 10 });
+        assert_eq!(send_offsets(&schedule), [-20, -10, 0, 10, 20]);
+        let schedule = plan_with(&[account(2), account(2)], &Spread::Centred { step: 10 });
+        assert_eq!(send_offsets(&schedule), [-15, -5, 5, 15]);
+    }
+
+    #[test]
+    fn explicit() {
+        let spread = Spread::Explicit {
+            offsets: vec![-5, 0, 5, 40],
+        };
+        let schedule = plan_with(&[account(3)], &spread);
+        assert_eq!(send_offsets(&schedule), [-5, 0, 5]);
+        assert_eq!(schedule.barrier, 3);
+        let schedule = plan_with(&[account(2), account(2)], &spread);
+        assert_eq!(send_offsets(&schedule), [-5, 0, 5, 40]);
+        assert_eq!(schedule.barrier, 0);
+    }
+
+    #[test]
+    fn explicit_without_enough_offsets() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let spread = Spread::Explicit {
+            offsets: vec![0, 10],
+        };
+        assert!(plan(&[account(3)], &spread, CONNECT_LEAD_TIME, &mut rng).is_err());
+        let spread = Spread::Explicit { offsets: vec![] };
+        assert!(plan(&[account(1)], &spread, CONNECT_LEAD_TIME, &mut rng).is_err());
+    }
+
+    #[test]
+    fn groups() {
+        let accounts = [account(3), account(2)];
+        let schedule = plan_with(&accounts, &Spread::Groups { step: 5, gap: 100 });
+        assert_eq!(send_offsets(&schedule), [0, 5, 10, 100, 105]);
+        assert_eq!(schedule.barrier, 0);
+    }
+
+    #[test]
+    fn jitter() {
+        let accounts = [account(5), account(5)];
+        let schedule = plan_with(&accounts, &Spread::Jitter { window: 30 });
+        assert!(send_offsets(&schedule)
+            .iter()
+            .all(|offset| (0..=30).contains(offset)));
+        let schedule = plan_with(&accounts, &Spread::Jitter { window: 0 });
+        assert_eq!(send_offsets(&schedule), [0; 10]);
+    }
+
+    #[test]
+    fn account_overrides() {
+        let accounts = [
+            account(2),
+            AccountSchedule {
+                snipe_time: snipe_time() - Duration::milliseconds(50),
+                requests: 2,
+                spread: Some(Spread::Linear { step: 3 }),
+            },
+            account(2),
+        ];
+        let schedule = plan_with(&accounts, &Spread::Linear { step: 10 });
+        // The account with its own spread is left out of the shared one
+        assert_eq!(send_offsets(&schedule), [0, 10, -50, -47, 20, 30]);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::{
+    clock::{self, ClockSkew},
+    config::{Account, ClockCheck, Endpoints, Overrides, Skin, SnipeTask, Spread},
+    constants::TOKEN_EXPIRY_MARGIN,
+    msauth::{self, DeviceCode, DeviceSignIn},
+    requests::{DroptimeData, Requests},
+    scheduler::{self, AccountSchedule, Schedule},
+    sockets::{self, ReconnectHandler, ResData},
+};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+
+/// A single name snipe scheduled for a droptime.
+pub struct SnipeJob {
+    pub name: String,
+    pub droptime: DateTime<Utc>,
+    pub offset: u32,
+    pub spread: Spread,
+    /// Milliseconds spent busy-waiting before each send for more precise timing.
+    pub spin_wait: u32,
+    /// Seconds before the send time each request connects.
+    pub lead_time: u32,
+    pub task: SnipeTask,
+    /// Server clock minus local clock, which the snipe time is corrected by.
+    pub skew: Duration,
+}
+
+impl SnipeJob {
+    /// The droptime according to the local clock.
+    #[must_use]
+    pub fn local_droptime(&self) -> DateTime<Utc> {
+        self.droptime - self.skew
+    }
+
+    /// The instant the first snipe request is sent.
+    #[must_use]
+    pub fn snipe_time(&self) -> DateTime<Utc> {
+        self.local_droptime() - Duration::milliseconds(i64::from(self.offset))
+    }
+
+    /// The instant bearer tokens used for the snipe must stay valid until.
+    #[must_use]
+    pub fn token_deadline(&self) -> DateTime<Utc> {
+        self.snipe_time() + Duration::seconds(TOKEN_EXPIRY_MARGIN)
+    }
+
+    /// The instant accounts should be authenticated before the snipe.
+    #[must_use]
+    pub fn setup_time(&self) -> DateTime<Utc> {
+        self.snipe_time() - Duration::hours(12)
+    }
+
+    #[must_use]
+    pub fn is_gc(&self) -> bool {
+        self.task == SnipeTask::Giftcode
+    }
+
+    /// Plans the requests sent by accounts with the given overrides. Jittered send times
+    /// differ every time this is called.
+    pub fn schedule(&self, overrides: &[Overrides]) -> Result<Schedule> {
+        let accounts: Vec<AccountSchedule> = overrides
+            .iter()
+            .map(|overrides| AccountSchedule {
+                snipe_time: self.local_droptime()
+                    - Duration::milliseconds(i64::from(overrides.offset.unwrap_or(self.offset))),
+                requests: overrides
+                    .requests
+                    .unwrap_or_else(|| scheduler::request_count(self.is_gc())),
+                spread: overrides.spread.clone(),
+            })
+            .collect();
+        scheduler::plan(
+            &accounts,
+            &self.spread,
+            self.lead_time,
+            &mut rand::thread_rng(),
+        )
+    }
+}
+
+/// Authenticates accounts and executes snipes against the Minecraft services API.
+pub struct Sniper {
+    requestor: Requests,
+    endpoints: Endpoints,
+}
+
+impl Sniper {
+    pub fn new(endpoints: &Endpoints) -> Result<Self> {
+        Ok(Self {
+            requestor: Requests::new(endpoints)?,
+            endpoints: endpoints.clone(),
+        })
+    }
+
+    pub async fn droptime(&self, name: &str) -> Result<DroptimeData> {
+        self.requestor
+            .check_name_availability_time(name)
+            .await
+            .with_context(|| format!("Failed to get the droptime of {name}"))
+    }
+
+    /// Returns a bearer token for `account`, signing in with the method `task` requires
+    /// unless the account already provides one.
+    pub async fn authenticate(&self, account: &Account, task: SnipeTask) -> Result<String> {
+        if let Some(bearer) = &account.bearer {
+            return Ok(bearer.clone());
+        }
+        let Some(email) = &account.email else {
+            bail!("The account has neither a bearer token nor an email");
+        };
+        if account.device_code {
+            bail!("{email} signs in with a device code, which has to be entered by hand");
+        }
+        let Some(password) = &account.password else {
+            bail!("{email} has no password to sign in with");
+        };
+        if task == SnipeTask::Mojang {
+            self.requestor
+                .authenticate_mojang(email, password, account.sq_ans.as_ref())
+                .await
+                .with_context(|| format!("Failed to authenticate the Mojang account {email}"))
+        } else {
+            let authenticator = msauth::Auth::new(email, password, &self.endpoints)
+                .with_context(|| "Error creating Microsoft authenticator")?;
+            authenticator
+                .authenticate()
+                .await
+                .with_context(|| format!("Failed to authenticate the Microsoft account {email}"))
+        }
+    }
+
+    /// Starts a device code sign in. The user signs in by entering the returned code.
+    pub async fn request_device_code(&self) -> Result<DeviceCode> {
+        msauth::DeviceCodeAuth::new(&self.endpoints)?
+            .request_code()
+            .await
+            .with_context(|| "Failed to request a device code")
+    }
+
+    pub async fn wait_for_device_code(&self, code: &DeviceCode) -> Result<DeviceSignIn> {
+        msauth::DeviceCodeAuth::new(&self.endpoints)?
+            .wait_for_code(code)
+            .await
+    }
+
+    /// Signs in again with the refresh token of an earlier device code sign in.
+    pub async fn refresh_device_sign_in(&self, refresh_token: &str) -> Result<DeviceSignIn> {
+        msauth::DeviceCodeAuth::new(&self.endpoints)?
+            .refresh(refresh_token)
+            .await
+    }
+
+    pub async fn check_clock(&self, clock_check: &ClockCheck) -> Result<ClockSkew> {
+        clock::measure(clock_check, &self.endpoints).await
+    }
+
+    pub async fn check_name_change_eligibility(&self, bearer_token: &str) -> Result<()> {
+        self.requestor
+            .check_name_change_eligibility(bearer_token)
+            .await
+    }
+
+    /// Sends the requests of `schedule`, which was planned by `job` for the accounts behind
+    /// `bearer_tokens`. `on_reconnect` is called whenever a request loses its connection
+    /// before the send time and reconnects.
+    pub async fn snipe(
+        &self,
+        job: &SnipeJob,
+        bearer_tokens: &[String],
+        schedule: &Schedule,
+        on_reconnect: &ReconnectHandler,
+    ) -> Result<Vec<ResData>> {
+        sockets::snipe_executor(
+            &job.name,
+            bearer_tokens,
+            schedule,
+            job.is_gc(),
+            job.spin_wait,
+            &self.endpoints,
+            on_reconnect,
+        )
+        .await
+        .with_context(|| format!("Failed to execute the snipe of {}", job.name))
+    }
+
+    pub async fn change_skin(&self, bearer_token: &str, skin: &Skin) -> Result<()> {
+        let skin_model = if skin.slim { "slim" } else { "classic" }.to_string();
+        self.requestor
+            .upload_skin(bearer_token, &skin.path, skin_model, skin.file)
+            .await
+    }
+}
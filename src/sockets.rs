@@ -1,109 +1,320 @@
-use crate::constants::BARRIER_THRESHOLD;
-use anyhow::Result;
-use chrono::{DateTime, Duration, Local};
+use crate::{
+    clock::Anchor,
+    config::Endpoints,
+    constants::{FINAL_BYTES, MAX_RECONNECTS, RESPONSE_TIMEOUT},
+    http::{self, HttpResponse},
+    scheduler::Schedule,
+};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Duration, Utc};
 use native_tls::TlsConnector;
+use reqwest::Url;
 use serde_json::json;
-use std::{net::ToSocketAddrs, sync::Arc};
+use std::{
+    fmt,
+    net::{SocketAddr, ToSocketAddrs},
+    sync::Arc,
+    time::Instant,
+};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::TcpStream,
     sync::Barrier,
-    time::sleep,
+    time::{sleep_until, timeout},
 };
+use tokio_native_tls::TlsStream;
 
 pub struct ResData {
-    pub status: u16,
-    pub timestamp: DateTime<Local>,
+    pub outcome: RequestOutcome,
+    pub timestamp: DateTime<Utc>,
     pub account_idx: usize,
+    pub response: Option<HttpResponse>,
+    /// When the final bytes of the request were scheduled to be sent.
+    pub send_time: DateTime<Utc>,
+    pub timeline: Timeline,
+}
+
+/// When each step of a snipe request happened. Steps that were never reached are `None`.
+#[derive(Default)]
+pub struct Timeline {
+    pub connect_start: Option<DateTime<Utc>>,
+    pub connected: Option<DateTime<Utc>>,
+    pub handshake_done: Option<DateTime<Utc>>,
+    /// When everything but the final bytes of the request was written.
+    pub head_written: Option<DateTime<Utc>>,
+    /// When the final bytes of the request were sent.
+    pub sent: Option<DateTime<Utc>>,
+    /// When the first byte of the response was read. Responses are only read once every
+    /// request has been sent if the barrier is in use.
+    pub first_byte: Option<DateTime<Utc>>,
+    /// Connections that were lost while waiting for the send time. The connection steps
+    /// above are those of the last connection.
+    pub reconnects: Vec<Reconnect>,
+}
+
+/// A connection that was re-established because it was lost before the send time.
+pub struct Reconnect {
+    /// When the lost connection was noticed.
+    pub at: DateTime<Utc>,
+    pub reason: String,
+}
+
+/// Called with the index of the account and the index of its request whenever a snipe
+/// request reconnects.
+pub type ReconnectHandler = Arc<dyn Fn(usize, usize, &Reconnect) + Send + Sync>;
+
+impl Timeline {
+    #[must_use]
+    pub fn handshake(&self) -> Option<Duration> {
+        Some(self.handshake_done? - self.connected?)
+    }
+}
+
+impl ResData {
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        matches!(self.outcome, RequestOutcome::Status(200))
+    }
+}
+
+/// What happened to a single snipe request.
+pub enum RequestOutcome {
+    ConnectFailed(String),
+    HandshakeFailed(String),
+    WriteFailed(String),
+    /// The connection was lost `MAX_RECONNECTS` times before the send time, last for the
+    /// given reason.
+    ConnectionLost(String),
+    ReadFailed(String),
+    Timeout,
+    Status(u16),
+}
+
+impl fmt::Display for RequestOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ConnectFailed(error) => write!(f, "connect failed: {error}"),
+            Self::HandshakeFailed(error) => write!(f, "TLS handshake failed: {error}"),
+            Self::WriteFailed(error) => write!(f, "write failed: {error}"),
+            Self::ConnectionLost(reason) => write!(
+                f,
+                "connection lost {MAX_RECONNECTS} times before the send time, last because of: {reason}"
+            ),
+            Self::ReadFailed(error) => write!(f, "read failed: {error}"),
+            Self::Timeout => write!(f, "timed out"),
+            Self::Status(status) => write!(f, "{status}"),
+        }
+    }
+}
+
+/// The Minecraft services host that snipe requests are sent to.
+pub struct Target {
+    pub host: String,
+    pub addr: SocketAddr,
+    pub base_path: String,
+    pub connector: tokio_native_tls::TlsConnector,
+}
+
+impl Target {
+    pub fn new(endpoints: &Endpoints) -> Result<Self> {
+        let url = Url::parse(&endpoints.minecraft_services)?;
+        if url.scheme() != "https" {
+            bail!("Snipe requests can only be sent over HTTPS");
+        }
+        let host = url.host_str().unwrap().to_string();
+        let port = url.port_or_known_default().unwrap();
+        let addr = (host.as_str(), port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("Unable to resolve {host}"))?;
+        let base_path = url.path().trim_end_matches('/').to_string();
+        let connector = TlsConnector::builder()
+            .danger_accept_invalid_certs(endpoints.accept_invalid_certs)
+            .build()?;
+        Ok(Self {
+            host,
+            addr,
+            base_path,
+            connector: tokio_native_tls::TlsConnector::from(connector),
+        })
+    }
 }
 
 pub async fn snipe_executor(
     name: &str,
     bearer_tokens: &[String],
-    snipe_time: DateTime<Local>,
+    schedule: &Schedule,
     is_gc: bool,
-    spread: u32,
+    spin_wait: u32,
+    endpoints: &Endpoints,
+    on_reconnect: &ReconnectHandler,
 ) -> Result<Vec<ResData>> {
-    let req_count = if is_gc { 5 } else { 3 };
-    let addr = "api.minecraftservices.com:443"
-        .to_socket_addrs()?
-        .next()
-        .unwrap();
-    let cx = TlsConnector::builder().build()?;
-    let cx = tokio_native_tls::TlsConnector::from(cx);
-    let cx = Arc::new(cx);
-    let mut handles = Vec::with_capacity(req_count * bearer_tokens.len());
-    let barrier_count = if spread <= BARRIER_THRESHOLD {
-        req_count * bearer_tokens.len()
-    } else {
-        0
-    };
-    let barrier = Arc::new(Barrier::new(barrier_count));
-    let mut snipe_time = snipe_time;
-    for (account_idx, bearer_token) in bearer_tokens.iter().enumerate() {
-        let payload = if is_gc {
-            let post_body = json!({ "profileName": name }).to_string();
-            format!("POST /minecraft/profile HTTP/1.1\r\nHost: api.minecraftservices.com\r\nConnection: close\r\nAuthorization: Bearer {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}", bearer_token, post_body.len(), post_body).into_bytes()
-        } else {
-            format!("PUT /minecraft/profile/name/{} HTTP/1.1\r\nHost: api.minecraftservices.com\r\nConnection: close\r\nAuthorization: Bearer {}\r\n", name, bearer_token).into_bytes()
-        };
-        let payload = Arc::new(payload);
-        for _ in 0..req_count {
-            let cx = Arc::clone(&cx);
-            let payload = Arc::clone(&payload);
-            let c = barrier.clone();
-            let mut buf = [0; 12];
-            let handshake_time = snipe_time - Duration::seconds(32);
-            let handle = tokio::task::spawn(async move {
-                let sleep_duration = (handshake_time - Local::now())
-                    .to_std()
-                    .unwrap_or(std::time::Duration::ZERO);
-                sleep(sleep_duration).await;
-                let socket = TcpStream::connect(&addr)
-                    .await
-                    .expect("Failed to establish a TCP connection with api.minecraftservices.com");
-                let mut socket = cx
-                    .connect("api.minecraftservices.com", socket)
-                    .await
-                    .expect("Failed to initiate a TLS handshake with api.minecraftservices.com");
-                socket
-                    .write_all(&payload)
-                    .await
-                    .expect("Failed to write to buffer");
-                let sleep_duration = (snipe_time - Local::now())
-                    .to_std()
-                    .unwrap_or(std::time::Duration::ZERO);
-                sleep(sleep_duration).await;
-                socket
-                    .write_all(b"\r\n")
-                    .await
-                    .expect("Failed to write to buffer");
-                c.wait().await;
-                socket
-                    .read_exact(&mut buf)
-                    .await
-                    .expect("Failed to read from buffer");
-                let timestamp = Local::now();
-                let res = String::from_utf8_lossy(&buf[..]);
-                let status: u16 = res[9..]
-                    .parse()
-                    .expect("Failed to parse HTTP status code from string");
-                ResData {
-                    status,
-                    timestamp,
-                    account_idx,
-                }
-            });
-            // Before you rag on me for not using +=, += doesn't work here
-            snipe_time = snipe_time + Duration::milliseconds(i64::from(spread));
-            handles.push(handle);
-        }
+    let spin_wait = std::time::Duration::from_millis(u64::from(spin_wait));
+    // Every wait from here on is measured on the monotonic clock
+    let anchor = Anchor::capture();
+    let target = Arc::new(Target::new(endpoints)?);
+    let (host, base_path) = (&target.host, &target.base_path);
+    let barrier = Arc::new(Barrier::new(schedule.barrier));
+    let payloads: Vec<_> = bearer_tokens
+        .iter()
+        .map(|bearer_token| {
+            let mut payload = if is_gc {
+                let post_body = json!({ "profileName": name }).to_string();
+                format!("POST {base_path}/minecraft/profile HTTP/1.1\r\nHost: {host}\r\nConnection: close\r\nAuthorization: Bearer {bearer_token}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{post_body}", post_body.len()).into_bytes()
+            } else {
+                format!("PUT {base_path}/minecraft/profile/name/{name} HTTP/1.1\r\nHost: {host}\r\nConnection: close\r\nAuthorization: Bearer {bearer_token}\r\n\r\n").into_bytes()
+            };
+            // The server can't act on the request until it has every byte, so the last ones
+            // are held back until the send time
+            let tail = payload.split_off(payload.len() - FINAL_BYTES);
+            Arc::new((payload, tail))
+        })
+        .collect();
+    let mut handles = Vec::with_capacity(schedule.requests.len());
+    for request in &schedule.requests {
+        let target = Arc::clone(&target);
+        let payload = Arc::clone(&payloads[request.account_idx]);
+        let c = barrier.clone();
+        let on_reconnect = Arc::clone(on_reconnect);
+        let (account_idx, request_idx, connect_time, send_time) = (
+            request.account_idx,
+            request.request_idx,
+            request.connect_time,
+            request.send_time,
+        );
+        let handle = tokio::task::spawn(async move {
+            sleep_until(anchor.instant(connect_time).into()).await;
+            let (head, tail) = payload.as_ref();
+            let mut timeline = Timeline::default();
+            let deadline = anchor.instant(send_time);
+            let socket = send_request(
+                &target,
+                (head, tail),
+                deadline,
+                spin_wait,
+                &anchor,
+                &mut timeline,
+                &|reconnect| on_reconnect(account_idx, request_idx, reconnect),
+            )
+            .await;
+            // Every task has to reach the barrier, even a failed one, or the rest hang
+            c.wait().await;
+            let (outcome, response) = match socket {
+                Ok(mut socket) => read_response(&mut socket, &anchor).await,
+                Err(outcome) => (outcome, None),
+            };
+            timeline.first_byte = response.as_ref().map(|response| response.received);
+            let timestamp = timeline.first_byte.unwrap_or_else(|| anchor.now());
+            ResData {
+                outcome,
+                timestamp,
+                account_idx,
+                response,
+                send_time,
+                timeline,
+            }
+        });
+        handles.push(handle);
     }
-    let mut res_vec = Vec::with_capacity(req_count * bearer_tokens.len());
+    let mut res_vec = Vec::with_capacity(handles.len());
     for handle in handles {
         let res_data = handle.await?;
         res_vec.push(res_data);
     }
-    res_vec.sort_unstable_by(|a, b| a.timestamp.cmp(&b.timestamp));
+    res_vec.sort_unstable_by_key(|a| a.timestamp);
     Ok(res_vec)
 }
+
+/// Connects ahead of the send time and sends the final bytes at `deadline`. A server or
+/// middlebox may close a connection that sits idle for too long, so the connection is
+/// watched while waiting and re-established if it is lost, calling `on_reconnect` each time.
+async fn send_request(
+    target: &Target,
+    (head, tail): (&[u8], &[u8]),
+    deadline: Instant,
+    spin_wait: std::time::Duration,
+    anchor: &Anchor,
+    timeline: &mut Timeline,
+    on_reconnect: &(dyn Fn(&Reconnect) + Send + Sync),
+) -> Result<TlsStream<TcpStream>, RequestOutcome> {
+    let mut socket = connect(target, head, anchor, timeline).await?;
+    loop {
+        let mut buf = [0; 1];
+        tokio::select! {
+            biased;
+            read = socket.read(&mut buf) => {
+                let reason = match read {
+                    Ok(0) => "connection closed by the server".to_string(),
+                    Ok(_) => "server responded before the request was complete".to_string(),
+                    Err(error) => error.to_string(),
+                };
+                if timeline.reconnects.len() == MAX_RECONNECTS {
+                    return Err(RequestOutcome::ConnectionLost(reason));
+                }
+                let reconnect = Reconnect {
+                    at: anchor.now(),
+                    reason,
+                };
+                on_reconnect(&reconnect);
+                timeline.reconnects.push(reconnect);
+                socket = connect(target, head, anchor, timeline).await?;
+            }
+            () = wait_until(deadline, spin_wait) => break,
+        }
+    }
+    timeline.sent = Some(anchor.now());
+    socket
+        .write_all(tail)
+        .await
+        .map_err(|error| RequestOutcome::WriteFailed(error.to_string()))?;
+    Ok(socket)
+}
+
+/// Opens a connection and writes everything but the final bytes of the request.
+async fn connect(
+    target: &Target,
+    head: &[u8],
+    anchor: &Anchor,
+    timeline: &mut Timeline,
+) -> Result<TlsStream<TcpStream>, RequestOutcome> {
+    timeline.connect_start = Some(anchor.now());
+    let socket = TcpStream::connect(target.addr)
+        .await
+        .map_err(|error| RequestOutcome::ConnectFailed(error.to_string()))?;
+    timeline.connected = Some(anchor.now());
+    let mut socket = target
+        .connector
+        .connect(&target.host, socket)
+        .await
+        .map_err(|error| RequestOutcome::HandshakeFailed(error.to_string()))?;
+    timeline.handshake_done = Some(anchor.now());
+    socket
+        .write_all(head)
+        .await
+        .map_err(|error| RequestOutcome::WriteFailed(error.to_string()))?;
+    timeline.head_written = Some(anchor.now());
+    Ok(socket)
+}
+
+/// Sleeps until `deadline`, spending the last `spin_wait` busy-waiting instead, since the
+/// tokio timer only has millisecond granularity. The busy-wait yields to the scheduler so
+/// that requests due at the same time don't starve each other.
+async fn wait_until(deadline: Instant, spin_wait: std::time::Duration) {
+    let now = Instant::now();
+    let wake = deadline.checked_sub(spin_wait).unwrap_or(now).max(now);
+    sleep_until(wake.into()).await;
+    while Instant::now() < deadline {
+        tokio::task::yield_now().await;
+    }
+}
+
+async fn read_response(
+    socket: &mut TlsStream<TcpStream>,
+    anchor: &Anchor,
+) -> (RequestOutcome, Option<HttpResponse>) {
+    match timeout(RESPONSE_TIMEOUT, http::read_response(socket, anchor)).await {
+        Err(_) => (RequestOutcome::Timeout, None),
+        Ok(Err(error)) => (RequestOutcome::ReadFailed(error.to_string()), None),
+        Ok(Ok(response)) => (RequestOutcome::Status(response.status), Some(response)),
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{config::SnipeTask, vault::write_private};
+use anyhow::{Context, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+#[derive(Serialize, Deserialize)]
+struct CachedToken {
+    /// Minecraft services API the token was issued by.
+    services: String,
+    mode: String,
+    email: String,
+    token: String,
+    expires: DateTime<Utc>,
+    /// Refresh token of a device code sign in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
+    /// Only kept in memory, as it was derived from credentials that never touch the disk.
+    #[serde(skip)]
+    transient: bool,
+}
+
+/// Bearer tokens of signed in accounts, so that accounts don't have to sign in again while
+/// their tokens are valid. Tokens are only handed out to runs against the same Minecraft
+/// services API and in the same mode as the run they were issued to.
+pub struct TokenCache {
+    path: PathBuf,
+    services: String,
+    mode: String,
+    tokens: Vec<CachedToken>,
+}
+
+impl TokenCache {
+    /// Creates an empty cache for tokens issued by `services` in `mode`, which is written to
+    /// `path` when saved.
+    #[must_use]
+    pub fn empty(path: &Path, services: &str, mode: SnipeTask) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            services: services.to_string(),
+            mode: mode.as_str().to_string(),
+            tokens: Vec::new(),
+        }
+    }
+
+    /// Reads the cache at `path`, which is empty if the file doesn't exist yet.
+    pub fn load(path: &Path, services: &str, mode: SnipeTask) -> Result<Self> {
+        let mut cache = Self::empty(path, services, mode);
+        if path.exists() {
+            let contents = read_to_string(path)?;
+            cache.tokens = serde_json::from_str(&contents)
+                .with_context(|| format!("Malformed token cache {}", path.display()))?;
+        }
+        Ok(cache)
+    }
+
+    fn find(&self, email: &str) -> Option<&CachedToken> {
+        self.tokens
+            .iter()
+            .find(|cached| self.matches(cached, email))
+    }
+
+    fn matches(&self, cached: &CachedToken, email: &str) -> bool {
+        cached.services == self.services && cached.mode == self.mode && cached.email == email
+    }
+
+    /// Returns the cached token of `email` if it stays valid until `valid_until`.
+    #[must_use]
+    pub fn get(&self, email: &str, valid_until: DateTime<Utc>) -> Option<&str> {
+        self.find(email)
+            .filter(|cached| cached.expires > valid_until)
+            .map(|cached| cached.token.as_str())
+    }
+
+    /// Returns the refresh token of the last device code sign in of `email`.
+    #[must_use]
+    pub fn refresh_token(&self, email: &str) -> Option<&str> {
+        self.find(email)?.refresh_token.as_deref()
+    }
+
+    /// Caches `token` for `email`, along with the refresh token of a device code sign in.
+    /// Tokens without an expiry aren't cached. Unless `persist` is set, the token is only
+    /// kept in memory and any token of `email` saved earlier is dropped from disk.
+    pub fn insert(&mut self, email: &str, token: &str, refresh_token: Option<&str>, persist: bool) {
+        let Some(expires) = expiry(token) else {
+            return;
+        };
+        let refresh_token = refresh_token
+            .map(str::to_string)
+            .or_else(|| self.find(email)?.refresh_token.clone());
+        let idx = self
+            .tokens
+            .iter()
+            .position(|cached| self.matches(cached, email));
+        let cached = CachedToken {
+            services: self.services.clone(),
+            mode: self.mode.clone(),
+            email: email.to_string(),
+            token: token.to_string(),
+            expires,
+            refresh_token,
+            transient: !persist,
+        };
+        match idx {
+            Some(idx) => self.tokens[idx] = cached,
+            None => self.tokens.push(cached),
+        }
+    }
+
+    /// Writes the cache to disk, readable only by the current user. Expired tokens are
+    /// dropped unless they come with a refresh token.
+    pub fn save(&self) -> Result<()> {
+        let now = Utc::now();
+        let tokens: Vec<&CachedToken> = self
+            .tokens
+            .iter()
+            .filter(|cached| !cached.transient)
+            .filter(|cached| cached.expires > now || cached.refresh_token.is_some())
+            .collect();
+        write_private(&self.path, serde_json::to_string(&tokens)?.as_bytes())
+    }
+}
+
+/// Reads the `exp` claim of a JWT bearer token without verifying its signature.
+#[must_use]
+pub fn expiry(token: &str) -> Option<DateTime<Utc>> {
+    #[derive(Deserialize)]
+    struct Claims {
+        exp: i64,
+    }
+    let payload = token.split('.').nth(1)?;
+    let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD).ok()?;
+    let claims: Claims = serde_json::from_slice(&payload).ok()?;
+    Utc.timestamp_opt(claims.exp, 0).single()
+}
@@ -0,0 +1,155 @@
+use crate::constants::{VAULT_KDF_ITERATIONS, VAULT_MAGIC};
+use anyhow::{anyhow, bail, Context, Result};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+};
+use zeroize::Zeroize;
+
+const SALT_LEN: usize = 16;
+
+/// Credentials of an account stored in the vault.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct VaultAccount {
+    pub label: String,
+    pub email: Option<String>,
+    pub password: Option<String>,
+    pub sq_ans: Option<[String; 3]>,
+    pub bearer: Option<String>,
+}
+
+/// Accounts encrypted with a passphrase. The accounts are only ever decrypted in memory.
+pub struct Vault {
+    path: PathBuf,
+    passphrase: String,
+    accounts: Vec<VaultAccount>,
+}
+
+impl Vault {
+    /// Creates an empty vault that is written to `path` when saved.
+    #[must_use]
+    pub fn create(path: &Path, passphrase: &str) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            passphrase: passphrase.to_string(),
+            accounts: Vec::new(),
+        }
+    }
+
+    /// Decrypts the vault at `path`.
+    pub fn open(path: &Path, passphrase: &str) -> Result<Self> {
+        let contents =
+            fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let header_len = VAULT_MAGIC.len() + SALT_LEN + NONCE_LEN;
+        if contents.len() < header_len || !contents.starts_with(VAULT_MAGIC) {
+            bail!("{} is not a buckshot vault", path.display());
+        }
+        let (header, ciphertext) = contents.split_at(header_len);
+        let salt = &header[VAULT_MAGIC.len()..VAULT_MAGIC.len() + SALT_LEN];
+        let nonce = Nonce::try_assume_unique_for_key(&header[VAULT_MAGIC.len() + SALT_LEN..])
+            .map_err(|_| anyhow!("Invalid vault nonce"))?;
+        let mut plaintext = ciphertext.to_vec();
+        let accounts = derive_key(passphrase, salt)
+            .open_in_place(nonce, Aad::from(header), &mut plaintext)
+            .map_err(|_| anyhow!("Wrong passphrase or corrupted vault"))
+            .and_then(|plaintext| {
+                serde_json::from_slice(plaintext).with_context(|| "Malformed vault contents")
+            });
+        plaintext.zeroize();
+        Ok(Self {
+            path: path.to_path_buf(),
+            passphrase: passphrase.to_string(),
+            accounts: accounts?,
+        })
+    }
+
+    /// Encrypts the vault with a fresh salt and nonce and replaces the vault file.
+    pub fn save(&self) -> Result<()> {
+        let rng = SystemRandom::new();
+        let mut header = VAULT_MAGIC.to_vec();
+        let mut salt = [0; SALT_LEN];
+        let mut nonce = [0; NONCE_LEN];
+        rng.fill(&mut salt)
+            .and_then(|()| rng.fill(&mut nonce))
+            .map_err(|_| anyhow!("Failed to generate random bytes"))?;
+        header.extend_from_slice(&salt);
+        header.extend_from_slice(&nonce);
+        let mut contents = serde_json::to_vec(&self.accounts)?;
+        derive_key(&self.passphrase, &salt)
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(&header),
+                &mut contents,
+            )
+            .map_err(|_| anyhow!("Failed to encrypt the vault"))?;
+        header.append(&mut contents);
+        write_private(&self.path, &header)
+    }
+
+    #[must_use]
+    pub fn accounts(&self) -> &[VaultAccount] {
+        &self.accounts
+    }
+
+    #[must_use]
+    pub fn get(&self, label: &str) -> Option<&VaultAccount> {
+        self.accounts.iter().find(|account| account.label == label)
+    }
+
+    pub fn add(&mut self, account: VaultAccount) -> Result<()> {
+        if self.get(&account.label).is_some() {
+            bail!(
+                "The vault already has an account labelled {}",
+                account.label
+            );
+        }
+        self.accounts.push(account);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, label: &str) -> Result<VaultAccount> {
+        let idx = self
+            .accounts
+            .iter()
+            .position(|account| account.label == label)
+            .with_context(|| format!("The vault has no account labelled {label}"))?;
+        Ok(self.accounts.remove(idx))
+    }
+}
+
+/// Replaces the file at `path` with a file only the current user can read.
+pub(crate) fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    let temp = path.with_extension("tmp");
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&temp)
+        .and_then(|mut file| file.write_all(contents))
+        .with_context(|| format!("Failed to write {}", temp.display()))?;
+    fs::rename(&temp, path).with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> LessSafeKey {
+    let mut key = [0; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(VAULT_KDF_ITERATIONS).unwrap(),
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    let unbound = UnboundKey::new(&CHACHA20_POLY1305, &key).unwrap();
+    key.zeroize();
+    LessSafeKey::new(unbound)
+}